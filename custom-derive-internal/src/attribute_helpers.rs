@@ -1,5 +1,7 @@
 use quote::ToTokens;
-use syn::{Attribute, Meta};
+use syn::{Attribute, ExprPath, Lit, Meta, NestedMeta, Type};
+
+use crate::case::RenameRule;
 
 pub fn contains_skip(attrs: &[Attribute]) -> bool {
     for attr in attrs.iter() {
@@ -11,3 +13,138 @@ pub fn contains_skip(attrs: &[Attribute]) -> bool {
     }
     false
 }
+
+pub fn contains_borsh_skip(attrs: &[Attribute]) -> bool {
+    for attr in attrs.iter() {
+        if let Ok(Meta::Path(path)) = attr.parse_meta() {
+            if path.to_token_stream().to_string().as_str() == "borsh_skip" {
+                return true;
+            }
+        }
+    }
+    false
+}
+
+/// Nested items of every `#[serde(...)]` attribute.
+fn serde_items(attrs: &[Attribute]) -> Vec<NestedMeta> {
+    let mut items = Vec::new();
+    for attr in attrs.iter() {
+        if let Ok(Meta::List(list)) = attr.parse_meta() {
+            if list.path.to_token_stream().to_string().as_str() == "serde" {
+                items.extend(list.nested);
+            }
+        }
+    }
+    items
+}
+
+/// Field-level `#[serde(skip)]` or `#[serde(skip_serializing)]`.
+pub fn contains_serde_skip(attrs: &[Attribute]) -> bool {
+    serde_items(attrs).iter().any(|nested| match nested {
+        NestedMeta::Meta(Meta::Path(path)) => {
+            matches!(path.to_token_stream().to_string().as_str(), "skip" | "skip_serializing")
+        }
+        _ => false,
+    })
+}
+
+/// `PhantomData<...>` markers carry no data and have no Borsh schema, so they are always skipped.
+pub fn is_phantom_data(ty: &Type) -> bool {
+    if let Type::Path(type_path) = ty {
+        if let Some(segment) = type_path.path.segments.last() {
+            return segment.ident == "PhantomData";
+        }
+    }
+    false
+}
+
+/// Field-level `#[custom_rename = "name"]`.
+pub fn get_rename(attrs: &[Attribute]) -> syn::Result<Option<String>> {
+    for attr in attrs.iter() {
+        if let Ok(Meta::NameValue(nv)) = attr.parse_meta() {
+            if nv.path.to_token_stream().to_string().as_str() == "custom_rename" {
+                return match &nv.lit {
+                    Lit::Str(s) => Ok(Some(s.value())),
+                    lit => Err(syn::Error::new_spanned(lit, "expected `#[custom_rename = \"...\"]`")),
+                };
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Field-level `#[serde(rename = "name")]`.
+pub fn get_serde_rename(attrs: &[Attribute]) -> syn::Result<Option<String>> {
+    for nested in serde_items(attrs).iter() {
+        if let NestedMeta::Meta(Meta::NameValue(nv)) = nested {
+            if nv.path.to_token_stream().to_string().as_str() == "rename" {
+                return match &nv.lit {
+                    Lit::Str(s) => Ok(Some(s.value())),
+                    lit => Err(syn::Error::new_spanned(lit, "expected `rename = \"...\"`")),
+                };
+            }
+        }
+    }
+    Ok(None)
+}
+
+fn get_path_attr(attrs: &[Attribute], name: &str) -> syn::Result<Option<ExprPath>> {
+    for attr in attrs.iter() {
+        if let Ok(Meta::NameValue(nv)) = attr.parse_meta() {
+            if nv.path.to_token_stream().to_string().as_str() == name {
+                return match &nv.lit {
+                    Lit::Str(s) => s.parse().map(Some),
+                    lit => Err(syn::Error::new_spanned(lit, format!("expected `#[{} = \"path\"]`", name))),
+                };
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Field-level `#[custom_serialize_with = "path::to::function"]`.
+pub fn get_serialize_with(attrs: &[Attribute]) -> syn::Result<Option<ExprPath>> {
+    get_path_attr(attrs, "custom_serialize_with")
+}
+
+/// Field-level `#[custom_skip_if = "path::to::predicate"]`.
+pub fn get_skip_if(attrs: &[Attribute]) -> syn::Result<Option<ExprPath>> {
+    get_path_attr(attrs, "custom_skip_if")
+}
+
+/// Field-level `#[custom_order = n]`.
+pub fn get_order(attrs: &[Attribute]) -> syn::Result<Option<u64>> {
+    for attr in attrs.iter() {
+        if let Ok(Meta::NameValue(nv)) = attr.parse_meta() {
+            if nv.path.to_token_stream().to_string().as_str() == "custom_order" {
+                return match &nv.lit {
+                    Lit::Int(n) => n.base10_parse().map(Some),
+                    lit => Err(syn::Error::new_spanned(lit, "expected `#[custom_order = n]`")),
+                };
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Container-level `#[custom(rename_all = "...")]`.
+pub fn get_rename_all(attrs: &[Attribute]) -> syn::Result<Option<RenameRule>> {
+    for attr in attrs.iter() {
+        if let Ok(Meta::List(list)) = attr.parse_meta() {
+            if list.path.to_token_stream().to_string().as_str() != "custom" {
+                continue;
+            }
+            for nested in list.nested.iter() {
+                if let NestedMeta::Meta(Meta::NameValue(nv)) = nested {
+                    if nv.path.to_token_stream().to_string().as_str() == "rename_all" {
+                        return match &nv.lit {
+                            Lit::Str(s) => RenameRule::from_lit(s).map(Some),
+                            lit => Err(syn::Error::new_spanned(lit, "expected `rename_all = \"...\"`")),
+                        };
+                    }
+                }
+            }
+        }
+    }
+    Ok(None)
+}
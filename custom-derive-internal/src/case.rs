@@ -0,0 +1,83 @@
+use syn::LitStr;
+
+/// Case conversion applied to every field name by `#[custom(rename_all = "...")]`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[allow(clippy::enum_variant_names)]
+pub enum RenameRule {
+    SnakeCase,
+    CamelCase,
+    PascalCase,
+    KebabCase,
+}
+
+impl RenameRule {
+    pub fn from_lit(lit: &LitStr) -> syn::Result<Self> {
+        match lit.value().as_str() {
+            "snake_case" => Ok(RenameRule::SnakeCase),
+            "camelCase" => Ok(RenameRule::CamelCase),
+            "PascalCase" => Ok(RenameRule::PascalCase),
+            "kebab-case" => Ok(RenameRule::KebabCase),
+            other => Err(syn::Error::new_spanned(
+                lit,
+                format!(
+                    "unknown rename rule `{}`, expected one of `snake_case`, `camelCase`, `PascalCase`, `kebab-case`",
+                    other
+                ),
+            )),
+        }
+    }
+
+    /// Converts a snake_case Rust field name according to this rule.
+    pub fn apply_to_field(&self, field: &str) -> String {
+        match self {
+            RenameRule::SnakeCase => field.to_owned(),
+            RenameRule::CamelCase => {
+                let pascal = RenameRule::PascalCase.apply_to_field(field);
+                let mut chars = pascal.chars();
+                match chars.next() {
+                    Some(first) => first.to_ascii_lowercase().to_string() + chars.as_str(),
+                    None => pascal,
+                }
+            }
+            RenameRule::PascalCase => {
+                let mut pascal = String::new();
+                let mut capitalize = true;
+                for ch in field.chars() {
+                    if ch == '_' {
+                        capitalize = true;
+                    } else if capitalize {
+                        pascal.push(ch.to_ascii_uppercase());
+                        capitalize = false;
+                    } else {
+                        pascal.push(ch);
+                    }
+                }
+                pascal
+            }
+            RenameRule::KebabCase => field.replace('_', "-"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn applies_each_rule() {
+        assert_eq!(RenameRule::SnakeCase.apply_to_field("first_name"), "first_name");
+        assert_eq!(RenameRule::CamelCase.apply_to_field("first_name"), "firstName");
+        assert_eq!(RenameRule::PascalCase.apply_to_field("first_name"), "FirstName");
+        assert_eq!(RenameRule::KebabCase.apply_to_field("first_name"), "first-name");
+        assert_eq!(RenameRule::CamelCase.apply_to_field("age"), "age");
+        assert_eq!(RenameRule::CamelCase.apply_to_field(""), "");
+    }
+
+    #[test]
+    fn parses_rule_names() {
+        let rule = |value: &str| RenameRule::from_lit(&LitStr::new(value, proc_macro2::Span::call_site()));
+        assert_eq!(rule("camelCase").unwrap(), RenameRule::CamelCase);
+        assert_eq!(rule("kebab-case").unwrap(), RenameRule::KebabCase);
+        assert!(rule("SCREAMING_SNAKE_CASE").is_err());
+    }
+}
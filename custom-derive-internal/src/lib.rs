@@ -1,6 +1,7 @@
 #![recursion_limit = "128"]
 
 mod attribute_helpers;
+mod case;
 mod struct_ser;
 
 pub use struct_ser::struct_ser;
@@ -2,9 +2,12 @@ use core::convert::TryFrom;
 
 use proc_macro2::{Span, TokenStream as TokenStream2};
 use quote::quote;
-use syn::{Fields, Index, ItemStruct, WhereClause};
+use syn::{Attribute, Fields, Index, ItemStruct, WhereClause};
 
-use crate::attribute_helpers::contains_skip;
+use crate::attribute_helpers::{
+    contains_borsh_skip, contains_serde_skip, contains_skip, get_order, get_rename, get_rename_all, get_serde_rename,
+    get_serialize_with, get_skip_if, is_phantom_data,
+};
 
 pub fn struct_ser(input: &ItemStruct) -> syn::Result<TokenStream2> {
     let name = &input.ident;
@@ -16,22 +19,59 @@ pub fn struct_ser(input: &ItemStruct) -> syn::Result<TokenStream2> {
         },
         Clone::clone,
     );
-    let mut body = TokenStream2::new();
+    let rename_all = get_rename_all(&input.attrs)?;
+    // Output order of each field's tokens; fields without `#[custom_order]` follow the ordered ones
+    let mut deltas: Vec<(Option<u64>, TokenStream2)> = Vec::new();
     match &input.fields {
         Fields::Named(fields) => {
             let mut field_index: usize = 0;
+            let mut path_names: Vec<String> = Vec::new();
             for field in &fields.named {
-                if contains_skip(&field.attrs) {
+                // Index into the Borsh schema fields, which only omits `#[borsh_skip]` fields
+                if contains_borsh_skip(&field.attrs) || is_phantom_data(&field.ty) {
+                    continue;
+                }
+                if contains_skip(&field.attrs) || contains_serde_skip(&field.attrs) {
+                    field_index += 1;
                     continue;
                 }
                 let field_name = field.ident.as_ref().unwrap();
+                let field_str = field_name.to_string().trim_start_matches("r#").to_owned();
+                let rename = match get_rename(&field.attrs)? {
+                    Some(rename) => Some(rename),
+                    None => get_serde_rename(&field.attrs)?,
+                };
+                let path_name = match rename {
+                    Some(rename) => rename,
+                    None => match rename_all {
+                        Some(rule) => rule.apply_to_field(&field_str),
+                        None => field_str,
+                    },
+                };
+                if path_names.contains(&path_name) {
+                    return Err(syn::Error::new_spanned(
+                        field,
+                        format!("duplicate field name `{}` after renaming", path_name),
+                    ));
+                }
+                path_names.push(path_name.clone());
+                if let Some(serialize_with) = get_serialize_with(&field.attrs)? {
+                    let delta = quote! {
+                        builder.stack_push(#field_index, #path_name)?;
+                        #serialize_with(&self.#field_name, builder)?;
+                        builder.stack_pop()?;
+                    };
+                    field_index += 1;
+                    deltas.push((get_order(&field.attrs)?, skip_if_guard(quote! { &self.#field_name }, &field.attrs, delta)?));
+                    continue;
+                }
                 let delta = quote! {
-                    CustomSerialize::push_node(&self.#field_name, builder, #field_index)?;
+                    CustomSerialize::push_node(&self.#field_name, builder, #field_index, #path_name)?;
                     CustomSerialize::serialize(&self.#field_name, builder)?;
                     CustomSerialize::pop_node(&self.#field_name, builder)?;
                 };
                 field_index += 1;
-                body.extend(delta);
+                deltas.push((get_order(&field.attrs)?, skip_if_guard(quote! { &self.#field_name }, &field.attrs, delta)?));
                 let field_type = &field.ty;
                 where_clause.predicates.push(
                     syn::parse2(quote! {
@@ -42,26 +82,73 @@ pub fn struct_ser(input: &ItemStruct) -> syn::Result<TokenStream2> {
             }
         }
         Fields::Unnamed(fields) => {
-            for field_idx in 0..fields.unnamed.len() {
+            let mut schema_index: usize = 0;
+            for (field_index, field) in fields.unnamed.iter().enumerate() {
+                if contains_borsh_skip(&field.attrs) || is_phantom_data(&field.ty) {
+                    continue;
+                }
+                if contains_skip(&field.attrs) || contains_serde_skip(&field.attrs) {
+                    schema_index += 1;
+                    continue;
+                }
                 let field_idx = Index {
-                    index: u32::try_from(field_idx).expect("up to 2^32 fields are supported"),
+                    index: u32::try_from(field_index).expect("up to 2^32 fields are supported"),
                     span: Span::call_site(),
                 };
+                let path_name = schema_index.to_string();
+                if let Some(serialize_with) = get_serialize_with(&field.attrs)? {
+                    let delta = quote! {
+                        builder.stack_push(#schema_index, #path_name)?;
+                        #serialize_with(&self.#field_idx, builder)?;
+                        builder.stack_pop()?;
+                    };
+                    schema_index += 1;
+                    deltas.push((get_order(&field.attrs)?, skip_if_guard(quote! { &self.#field_idx }, &field.attrs, delta)?));
+                    continue;
+                }
                 let delta = quote! {
+                    CustomSerialize::push_node(&self.#field_idx, builder, #schema_index, #path_name)?;
                     CustomSerialize::serialize(&self.#field_idx, builder)?;
+                    CustomSerialize::pop_node(&self.#field_idx, builder)?;
                 };
-                body.extend(delta);
+                schema_index += 1;
+                deltas.push((get_order(&field.attrs)?, skip_if_guard(quote! { &self.#field_idx }, &field.attrs, delta)?));
+                let field_type = &field.ty;
+                where_clause.predicates.push(
+                    syn::parse2(quote! {
+                        #field_type: CustomSerialize
+                    })
+                    .unwrap(),
+                );
             }
         }
         Fields::Unit => {}
     }
+    deltas.sort_by_key(|(order, _)| order.map_or((1, 0), |n| (0, n)));
+    let mut body = TokenStream2::new();
+    for (_, delta) in deltas {
+        body.extend(delta);
+    }
     Ok(quote! {
         impl #impl_generics CustomSerialize for #name #ty_generics #where_clause {
             fn serialize<B: Build>(&self, builder: &mut B) -> ::core::result::Result<(), borsh::maybestd::io::Error> {
-                builder.build(None);
+                builder.build(None)?;
                 #body
                 Ok(())
             }
         }
     })
 }
+
+/// Wraps a field's push/serialize/pop calls in `if !predicate(field)` for `#[custom_skip_if]`, so
+/// a skipped field leaves the builder stack untouched.
+fn skip_if_guard(field: TokenStream2, attrs: &[Attribute], delta: TokenStream2) -> syn::Result<TokenStream2> {
+    Ok(match get_skip_if(attrs)? {
+        Some(predicate) => quote! {
+            if !#predicate(#field) {
+                #delta
+            }
+        },
+        None => delta,
+    })
+}
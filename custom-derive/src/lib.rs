@@ -4,7 +4,23 @@ use syn::{ItemEnum, ItemStruct, ItemUnion};
 
 use custom_derive_internal::*;
 
-#[proc_macro_derive(CustomSerialize, attributes(custom_skip))]
+/// Fields of type `PhantomData<...>` are skipped automatically; any other field can be
+/// left out of the output with `#[custom_skip]`.
+///
+/// `#[serde(rename = "...")]`, `#[serde(skip)]` and `#[serde(skip_serializing)]` are honored as
+/// well. A field's name is taken from `#[custom_rename]` first, then `#[serde(rename)]`, then the
+/// container's `#[custom(rename_all)]` rule.
+///
+/// Fields are output in declaration order unless given `#[custom_order = n]`; ordered fields come
+/// first, by `n`, followed by the rest in declaration order.
+///
+/// `#[custom_serialize_with = "path"]` renders a field with
+/// `fn path<B: Build>(value: &T, builder: &mut B) -> Result<()>` instead of its `CustomSerialize` impl,
+/// and `#[custom_skip_if = "path"]` leaves a field out whenever `fn path(value: &T) -> bool` is true.
+#[proc_macro_derive(
+    CustomSerialize,
+    attributes(custom, custom_skip, custom_skip_if, custom_rename, custom_order, custom_serialize_with, serde)
+)]
 pub fn borsh_serialize(input: TokenStream) -> TokenStream {
     let res = if let Ok(input) = syn::parse::<ItemStruct>(input.clone()) {
         struct_ser(&input)
@@ -0,0 +1,10 @@
+use custom_derive::CustomSerialize;
+
+#[derive(CustomSerialize)]
+struct Person {
+    #[custom_rename = "name"]
+    first_name: String,
+    name: String,
+}
+
+fn main() {}
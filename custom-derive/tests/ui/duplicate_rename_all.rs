@@ -0,0 +1,11 @@
+use custom_derive::CustomSerialize;
+
+#[derive(CustomSerialize)]
+#[custom(rename_all = "camelCase")]
+struct Person {
+    first_name: String,
+    #[serde(rename = "firstName")]
+    given: String,
+}
+
+fn main() {}
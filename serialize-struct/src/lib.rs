@@ -0,0 +1,8 @@
+pub mod serialize;
+
+/// Paths used by `schema!` expansions, so callers don't need Borsh as a direct dependency.
+#[doc(hidden)]
+pub mod __private {
+    pub use borsh;
+    pub use borsh_derive;
+}
@@ -1,12 +1,11 @@
 use borsh::{BorshSerialize, BorshDeserialize};
 use borsh_derive::{BorshSchema};
 
-mod serialize;
-use serialize::{CustomSerialize, schema::{get_schema, TypeIterator}};
+use dynamic_struct::serialize::{CustomSerialize, avro::to_avro, openapi::to_openapi_components, schema::{get_schema, TypeIterator}};
 
 use custom_derive::CustomSerialize;
 
-use crate::serialize::Build;
+use dynamic_struct::serialize::Build;
 
 #[derive(BorshSerialize, BorshDeserialize, BorshSchema, Debug, Clone, CustomSerialize)]
 struct Person {
@@ -30,6 +29,10 @@ where
 // End data schema
 
 fn main() {
+    // Library diagnostics are only emitted with the `tracing` feature and a subscriber installed.
+    #[cfg(feature = "tracing")]
+    tracing_subscriber::fmt().with_max_level(tracing::Level::DEBUG).init();
+
     //let person = Person { name: "Alison".into(), uuid: 30, cool: true, vector: vec![(100, 200, "Hello".into())] };
     //let person = Person { name: "Alison".into(), uuid: 30, cool: true };
     let person = Person { uuid: 30 };
@@ -46,4 +49,6 @@ fn main() {
         println!("");
     }
     person.try_to_custom(&tsch).unwrap();
+    println!("{}", to_openapi_components(&tsch));
+    println!("{}", to_avro(&tsch));
 }
@@ -3,49 +3,100 @@ use borsh::maybestd::{
     //boxed::Box,
     //collections::{BTreeMap, BTreeSet, BinaryHeap, HashMap, HashSet, LinkedList, VecDeque},
     //io::{ErrorKind, Result, Write},
-    io::Result,
+    io::{Error, ErrorKind, Result},
     //string::String,
     //vec::Vec,
 };
 
+pub mod avro;
+pub mod capnp;
+pub mod columns;
+#[cfg(feature = "graphql")]
+pub mod graphql;
+pub mod openapi;
+pub mod python;
+pub mod registry;
 pub mod schema;
 use schema::*;
 
+#[cfg(feature = "tracing")]
+use tracing::{debug, debug_span};
+
+/// Visitor driven by `CustomSerialize::serialize`. Containers call `build(None)`, primitives call
+/// `build(Some(value))`, and every field or element is wrapped in `stack_push`/`stack_pop`.
 pub trait Build {
     fn build(&mut self, debug: Option<&str>) -> Result<()>;
-    fn stack_push(&mut self, index: usize) -> Result<()>;
+    fn stack_push(&mut self, index: usize, name: &str) -> Result<()>;
     fn stack_pop(&mut self) -> Result<()>;
 }
 
 pub struct Builder<'a> {
     schema: &'a TypeSchema,
     stack: Vec<&'a Type>,
+    path: Vec<String>,
 }
 
 impl<'a> Build for Builder<'a> {
     fn build(&mut self, debug: Option<&str>) -> Result<()> {
-        let top_index = self.stack.len() - 1;
-        let node = self.stack[top_index];
-        println!("Type: {:?}", node);
-        match node.datatype {
-            DataType::Struct => {},
-            _ => {
-                println!("{}", debug.unwrap());
+        #[cfg(feature = "tracing")]
+        {
+            let top_index = self.stack.len() - 1;
+            let node = self.stack[top_index];
+            if node.datatype.is_container() {
+                debug!(path = %self.path.join("."), datatype = ?node.datatype, term = ?node.term, "container");
+            } else {
+                let value = debug.unwrap_or_default();
+                debug!(path = %self.path.join("."), value, "value");
             }
         }
+        #[cfg(not(feature = "tracing"))]
+        let _ = debug;
         Ok(())
     }
 
-    fn stack_push(&mut self, index: usize) -> Result<()> {
+    fn stack_push(&mut self, index: usize, name: &str) -> Result<()> {
         let top_index = self.stack.len() - 1;
         let top_node = self.stack[top_index];
-        let field = &top_node.fields.as_ref().unwrap()[index];
+        let mut children = top_node.children(self.schema);
+        let field = match top_node.datatype {
+            // Every element of a sequence shares the single element type
+            DataType::Vec | DataType::Array | DataType::HashSet => children.next(),
+            _ => children.nth(index),
+        };
+        let field = field.ok_or_else(|| {
+            Error::new(ErrorKind::InvalidData, format!("No field at index {}: {}", index, name))
+        })?;
         self.stack.push(field);
+        self.path.push(name.to_owned());
         Ok(())
     }
 
     fn stack_pop(&mut self) -> Result<()> {
         self.stack.pop();
+        self.path.pop();
+        Ok(())
+    }
+}
+
+/// Counts the primitive values in an instance without consulting a schema.
+#[derive(Debug, Default)]
+pub struct PrimitiveCounter {
+    pub count: usize,
+}
+
+impl Build for PrimitiveCounter {
+    fn build(&mut self, debug: Option<&str>) -> Result<()> {
+        if debug.is_some() {
+            self.count += 1;
+        }
+        Ok(())
+    }
+
+    fn stack_push(&mut self, _index: usize, _name: &str) -> Result<()> {
+        Ok(())
+    }
+
+    fn stack_pop(&mut self) -> Result<()> {
         Ok(())
     }
 }
@@ -54,16 +105,19 @@ pub trait CustomSerialize {
     fn serialize<B: Build>(&self, builder: &mut B) -> Result<()>;
 
     fn try_to_custom(&self, schema: &TypeSchema) -> Result<()> {
+        #[cfg(feature = "tracing")]
+        let _span = debug_span!("try_to_custom", term = ?schema.root().term).entered();
         let mut b = Builder {
             schema,
-            stack: vec![&schema.schema],
+            stack: vec![&schema.root()],
+            path: Vec::new(),
         };
         self.serialize(&mut b)?;
         Ok(())
     }
 
-    fn push_node<B: Build>(&self, builder: &mut B, index: usize) -> Result<()> {
-        builder.stack_push(index)?;
+    fn push_node<B: Build>(&self, builder: &mut B, index: usize, name: &str) -> Result<()> {
+        builder.stack_push(index, name)?;
         Ok(())
     }
 
@@ -73,11 +127,399 @@ pub trait CustomSerialize {
     }
 }
 
-impl CustomSerialize for u8 {
+macro_rules! impl_for_primitives {
+    ($($type:ident)+) => {
+    $(
+        impl CustomSerialize for $type {
+            #[inline]
+            fn serialize<B: Build>(&self, builder: &mut B) -> Result<()> {
+                builder.build(Some(format!("{}: {:?}", stringify!($type), self).as_str()))
+            }
+        }
+    )+
+    };
+}
+
+impl_for_primitives!(bool f32 f64 i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 String);
+
+/// Unit carries no value, so nothing is built. As an element or field it is still pushed and
+/// popped, which keeps the indices of its siblings intact.
+impl CustomSerialize for () {
     #[inline]
+    fn serialize<B: Build>(&self, _builder: &mut B) -> Result<()> {
+        Ok(())
+    }
+}
+
+macro_rules! impl_for_nonzero_integer {
+    ($type: ty) => {
+        impl CustomSerialize for $type {
+            #[inline]
+            fn serialize<B: Build>(&self, builder: &mut B) -> Result<()> {
+                self.get().serialize(builder)
+            }
+        }
+    };
+}
+
+impl_for_nonzero_integer!(core::num::NonZeroI8);
+impl_for_nonzero_integer!(core::num::NonZeroI16);
+impl_for_nonzero_integer!(core::num::NonZeroI32);
+impl_for_nonzero_integer!(core::num::NonZeroI64);
+impl_for_nonzero_integer!(core::num::NonZeroI128);
+impl_for_nonzero_integer!(core::num::NonZeroU8);
+impl_for_nonzero_integer!(core::num::NonZeroU16);
+impl_for_nonzero_integer!(core::num::NonZeroU32);
+impl_for_nonzero_integer!(core::num::NonZeroU64);
+impl_for_nonzero_integer!(core::num::NonZeroU128);
+
+impl<T> CustomSerialize for Vec<T>
+where
+    T: CustomSerialize,
+{
+    fn serialize<B: Build>(&self, builder: &mut B) -> Result<()> {
+        builder.build(None)?;
+        for (index, item) in self.iter().enumerate() {
+            item.push_node(builder, index, index.to_string().as_str())?;
+            item.serialize(builder)?;
+            item.pop_node(builder)?;
+        }
+        Ok(())
+    }
+}
+
+/// `Some` pushes its value as element `0` of the option node, the single child the schema gives
+/// it. `None` only builds the container.
+impl<T> CustomSerialize for Option<T>
+where
+    T: CustomSerialize,
+{
     fn serialize<B: Build>(&self, builder: &mut B) -> Result<()> {
-        builder.build(Some(format!("u8: {:?}", self).as_str()));
+        builder.build(None)?;
+        if let Some(value) = self {
+            value.push_node(builder, 0, "0")?;
+            value.serialize(builder)?;
+            value.pop_node(builder)?;
+        }
         Ok(())
     }
 }
 
+macro_rules! impl_tuple {
+    ($($idx:tt $name:ident)+) => {
+    impl<$($name),+> CustomSerialize for ($($name,)+)
+    where
+        $($name: CustomSerialize,)+
+    {
+        #[inline]
+        fn serialize<B: Build>(&self, builder: &mut B) -> Result<()> {
+            builder.build(None)?;
+            $(
+                self.$idx.push_node(builder, $idx, stringify!($idx))?;
+                self.$idx.serialize(builder)?;
+                self.$idx.pop_node(builder)?;
+            )+
+            Ok(())
+        }
+    }
+    };
+}
+
+impl_tuple!(0 T0);
+impl_tuple!(0 T0 1 T1);
+impl_tuple!(0 T0 1 T1 2 T2);
+impl_tuple!(0 T0 1 T1 2 T2 3 T3);
+impl_tuple!(0 T0 1 T1 2 T2 3 T3 4 T4);
+impl_tuple!(0 T0 1 T1 2 T2 3 T3 4 T4 5 T5);
+impl_tuple!(0 T0 1 T1 2 T2 3 T3 4 T4 5 T5 6 T6);
+impl_tuple!(0 T0 1 T1 2 T2 3 T3 4 T4 5 T5 6 T6 7 T7);
+impl_tuple!(0 T0 1 T1 2 T2 3 T3 4 T4 5 T5 6 T6 7 T7 8 T8);
+impl_tuple!(0 T0 1 T1 2 T2 3 T3 4 T4 5 T5 6 T6 7 T7 8 T8 9 T9);
+impl_tuple!(0 T0 1 T1 2 T2 3 T3 4 T4 5 T5 6 T6 7 T7 8 T8 9 T9 10 T10);
+impl_tuple!(0 T0 1 T1 2 T2 3 T3 4 T4 5 T5 6 T6 7 T7 8 T8 9 T9 10 T10 11 T11);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Records the path and rendering of every primitive value.
+    #[derive(Default)]
+    struct Recorder {
+        path: Vec<String>,
+        values: Vec<(String, String)>,
+    }
+
+    impl Build for Recorder {
+        fn build(&mut self, debug: Option<&str>) -> Result<()> {
+            if let Some(value) = debug {
+                self.values.push((self.path.join("."), value.to_owned()));
+            }
+            Ok(())
+        }
+
+        fn stack_push(&mut self, _index: usize, name: &str) -> Result<()> {
+            self.path.push(name.to_owned());
+            Ok(())
+        }
+
+        fn stack_pop(&mut self) -> Result<()> {
+            self.path.pop();
+            Ok(())
+        }
+    }
+
+    fn record<T: CustomSerialize>(value: &T) -> Vec<(String, String)> {
+        let mut recorder = Recorder::default();
+        value.serialize(&mut recorder).unwrap();
+        recorder.values
+    }
+
+    fn entry(path: &str, value: &str) -> (String, String) {
+        (path.to_owned(), value.to_owned())
+    }
+
+    #[derive(borsh_derive::BorshSchema, custom_derive::CustomSerialize)]
+    struct Other { label: String, flags: Vec<bool> }
+
+    #[derive(custom_derive::CustomSerialize)]
+    struct Person { name: String, age: u32, thing: Vec<(u8, Other)>, nothing: () }
+
+    fn sample() -> Person {
+        Person {
+            name: "Alison".into(),
+            age: 30,
+            thing: vec![(1, Other { label: "a".into(), flags: vec![true, false] }), (2, Other { label: "b".into(), flags: vec![] })],
+            nothing: (),
+        }
+    }
+
+    /// Records the datatype `Builder` resolved for every primitive value.
+    struct Resolved<'a> {
+        builder: Builder<'a>,
+        values: Vec<(String, DataType)>,
+    }
+
+    impl Build for Resolved<'_> {
+        fn build(&mut self, debug: Option<&str>) -> Result<()> {
+            if debug.is_some() {
+                let node = self.builder.stack.last().unwrap();
+                self.values.push((self.builder.path.join("."), node.datatype.clone()));
+            }
+            self.builder.build(debug)
+        }
+
+        fn stack_push(&mut self, index: usize, name: &str) -> Result<()> {
+            self.builder.stack_push(index, name)
+        }
+
+        fn stack_pop(&mut self) -> Result<()> {
+            self.builder.stack_pop()
+        }
+    }
+
+    fn resolve<T: CustomSerialize + borsh::BorshSchema>(value: &T) -> Vec<(String, DataType)> {
+        let schema = get_schema::<T>();
+        let mut resolved = Resolved { builder: Builder { schema: &schema, stack: vec![schema.root()], path: Vec::new() }, values: Vec::new() };
+        value.serialize(&mut resolved).unwrap();
+        // every push was matched by a pop
+        assert_eq!((resolved.builder.stack.len(), resolved.builder.path.len()), (1, 0));
+        resolved.values
+    }
+
+    fn resolved(path: &str, datatype: DataType) -> (String, DataType) {
+        (path.to_owned(), datatype)
+    }
+
+    #[derive(borsh_derive::BorshSchema, custom_derive::CustomSerialize)]
+    struct Wrapper<T> { value: T, count: u32 }
+
+    #[derive(borsh_derive::BorshSchema, custom_derive::CustomSerialize)]
+    struct Bag<T> { items: Vec<T> }
+
+    #[derive(borsh_derive::BorshSchema, custom_derive::CustomSerialize)]
+    struct Pair(u8, String);
+
+    #[test]
+    fn derive_supports_generic_structs() {
+        let wrapper = Wrapper { value: String::from("x"), count: 2 };
+        assert_eq!(record(&wrapper), vec![entry("value", "String: \"x\""), entry("count", "u32: 2")]);
+        assert_eq!(resolve(&wrapper), vec![resolved("value", DataType::String), resolved("count", DataType::Int)]);
+        assert!(wrapper.try_to_custom(&get_schema::<Wrapper<String>>()).is_ok());
+        let bag = Bag { items: vec![true, false] };
+        assert_eq!(record(&bag), vec![entry("items.0", "bool: true"), entry("items.1", "bool: false")]);
+        assert_eq!(resolve(&bag), vec![resolved("items.0", DataType::Bool), resolved("items.1", DataType::Bool)]);
+        assert!(bag.try_to_custom(&get_schema::<Bag<bool>>()).is_ok());
+    }
+
+    #[test]
+    fn derive_skips_phantom_data() {
+        #[derive(borsh_derive::BorshSchema, custom_derive::CustomSerialize)]
+        struct Tagged<T> { id: u32, #[borsh_skip] _marker: core::marker::PhantomData<T> }
+        let tagged = Tagged::<String> { id: 9, _marker: core::marker::PhantomData };
+        assert_eq!(record(&tagged), vec![entry("id", "u32: 9")]);
+        assert_eq!(resolve(&tagged), vec![resolved("id", DataType::Int)]);
+    }
+
+    #[test]
+    fn custom_skip_keeps_later_fields_aligned_with_the_schema() {
+        #[derive(borsh_derive::BorshSchema, custom_derive::CustomSerialize)]
+        #[allow(dead_code)]
+        struct Account { #[custom_skip] secret: String, balance: u64, owner: String }
+        let account = Account { secret: "hunter2".into(), balance: 5, owner: "Alison".into() };
+        assert_eq!(record(&account), vec![entry("balance", "u64: 5"), entry("owner", "String: \"Alison\"")]);
+        assert_eq!(resolve(&account), vec![resolved("balance", DataType::Int), resolved("owner", DataType::String)]);
+    }
+
+    #[test]
+    fn serde_attributes_rename_and_skip_fields() {
+        #[derive(borsh_derive::BorshSchema, custom_derive::CustomSerialize)]
+        #[allow(dead_code)]
+        struct User {
+            #[serde(rename = "userName")]
+            user_name: String,
+            #[serde(skip)]
+            session: String,
+            #[serde(skip_serializing)]
+            token: String,
+            #[custom_rename = "mail"]
+            #[serde(rename = "email")]
+            email_address: String,
+            age: u8,
+        }
+        let user = User { user_name: "alison".into(), session: "s".into(), token: "t".into(), email_address: "a@b.c".into(), age: 30 };
+        assert_eq!(record(&user), vec![
+            entry("userName", "String: \"alison\""),
+            entry("mail", "String: \"a@b.c\""),
+            entry("age", "u8: 30"),
+        ]);
+        assert_eq!(resolve(&user), vec![
+            resolved("userName", DataType::String),
+            resolved("mail", DataType::String),
+            resolved("age", DataType::Int),
+        ]);
+    }
+
+    #[test]
+    fn custom_order_reorders_output_but_not_schema_positions() {
+        #[derive(borsh_derive::BorshSchema, custom_derive::CustomSerialize)]
+        struct Row { flag: bool, #[custom_order = 2] label: String, count: u16, #[custom_order = 1] id: u64 }
+        let row = Row { flag: true, label: "x".into(), count: 3, id: 7 };
+        assert_eq!(record(&row), vec![
+            entry("id", "u64: 7"),
+            entry("label", "String: \"x\""),
+            entry("flag", "bool: true"),
+            entry("count", "u16: 3"),
+        ]);
+        assert_eq!(resolve(&row), vec![
+            resolved("id", DataType::Int),
+            resolved("label", DataType::String),
+            resolved("flag", DataType::Bool),
+            resolved("count", DataType::Int),
+        ]);
+    }
+
+    fn hex<B: Build>(value: &[u8; 4], builder: &mut B) -> Result<()> {
+        let hex: String = value.iter().map(|b| format!("{:02x}", b)).collect();
+        builder.build(Some(hex.as_str()))
+    }
+
+    #[test]
+    fn serialize_with_renders_a_field() {
+        #[derive(borsh_derive::BorshSchema, custom_derive::CustomSerialize)]
+        struct Block { #[custom_serialize_with = "hex"] hash: [u8; 4], height: u64 }
+        let block = Block { hash: [0xde, 0xad, 0xbe, 0xef], height: 12 };
+        assert_eq!(record(&block), vec![entry("hash", "deadbeef"), entry("height", "u64: 12")]);
+        assert_eq!(resolve(&block), vec![resolved("hash", DataType::Array), resolved("height", DataType::Int)]);
+    }
+
+    #[test]
+    fn skip_if_leaves_out_fields_and_keeps_the_stack_balanced() {
+        #[derive(borsh_derive::BorshSchema, custom_derive::CustomSerialize)]
+        struct Reading { #[custom_skip_if = "Option::is_none"] value: Option<u32>, unit: String }
+        let empty = Reading { value: None, unit: "C".into() };
+        assert_eq!(record(&empty), vec![entry("unit", "String: \"C\"")]);
+        assert_eq!(resolve(&empty), vec![resolved("unit", DataType::String)]);
+        let full = Reading { value: Some(21), unit: "C".into() };
+        assert_eq!(record(&full), vec![entry("value.0", "u32: 21"), entry("unit", "String: \"C\"")]);
+        assert_eq!(resolve(&full), vec![resolved("value.0", DataType::Int), resolved("unit", DataType::String)]);
+    }
+
+    #[test]
+    fn unit_values_keep_their_place_without_a_value() {
+        let units = vec![(), ()];
+        assert!(resolve(&units).is_empty());
+        assert!(units.try_to_custom(&get_schema::<Vec<()>>()).is_ok());
+        let pairs = vec![((), 1u8), ((), 2u8)];
+        assert_eq!(resolve(&pairs), vec![resolved("0.1", DataType::Int), resolved("1.1", DataType::Int)]);
+        #[derive(borsh_derive::BorshSchema, custom_derive::CustomSerialize)]
+        struct Marked { before: u8, nothing: (), after: String }
+        let marked = Marked { before: 1, nothing: (), after: "x".into() };
+        assert_eq!(resolve(&marked), vec![resolved("before", DataType::Int), resolved("after", DataType::String)]);
+        assert!(marked.try_to_custom(&get_schema::<Marked>()).is_ok());
+    }
+
+    #[test]
+    fn derive_supports_tuple_structs() {
+        let pair = Pair(4, String::from("four"));
+        assert_eq!(record(&pair), vec![entry("0", "u8: 4"), entry("1", "String: \"four\"")]);
+        assert_eq!(resolve(&pair), vec![resolved("0", DataType::Int), resolved("1", DataType::String)]);
+        assert!(pair.try_to_custom(&get_schema::<Pair>()).is_ok());
+    }
+
+    #[test]
+    fn builder_resolves_nested_tuples() {
+        let value = ((1u8, 2u8), String::from("x"));
+        assert_eq!(resolve(&value), vec![
+            ("0.0".to_owned(), DataType::Int),
+            ("0.1".to_owned(), DataType::Int),
+            ("1".to_owned(), DataType::String),
+        ]);
+        let value = (vec![7u32, 8], Other { label: "a".into(), flags: vec![true] });
+        assert_eq!(resolve(&value), vec![
+            ("0.0".to_owned(), DataType::Int),
+            ("0.1".to_owned(), DataType::Int),
+            ("1.label".to_owned(), DataType::String),
+            ("1.flags.0".to_owned(), DataType::Bool),
+        ]);
+        assert!(value.try_to_custom(&get_schema::<(Vec<u32>, Other)>()).is_ok());
+    }
+
+    #[test]
+    fn builder_resolves_tuples_inside_vecs() {
+        let value = vec![(Other { label: "a".into(), flags: vec![true] }, 1u8), (Other { label: "b".into(), flags: vec![] }, 2u8)];
+        assert_eq!(resolve(&value), vec![
+            resolved("0.0.label", DataType::String),
+            resolved("0.0.flags.0", DataType::Bool),
+            resolved("0.1", DataType::Int),
+            resolved("1.0.label", DataType::String),
+            resolved("1.1", DataType::Int),
+        ]);
+        assert!(value.try_to_custom(&get_schema::<Vec<(Other, u8)>>()).is_ok());
+    }
+
+    #[test]
+    fn rename_all_renames_field_paths() {
+        #[derive(custom_derive::CustomSerialize)]
+        #[custom(rename_all = "camelCase")]
+        struct Name { first_name: String, last_name: String }
+        let name = Name { first_name: "Alison".into(), last_name: "Smith".into() };
+        assert_eq!(record(&name), vec![entry("firstName", "String: \"Alison\""), entry("lastName", "String: \"Smith\"")]);
+    }
+
+    #[test]
+    fn primitive_counter_counts_leaf_values() {
+        let mut counter = PrimitiveCounter::default();
+        sample().serialize(&mut counter).unwrap();
+        // name, age, then per element the u8, the label and each flag
+        assert_eq!(counter.count, 2 + 3 + 1 + 2);
+        let mut counter = PrimitiveCounter::default();
+        Vec::<u64>::new().serialize(&mut counter).unwrap();
+        assert_eq!(counter.count, 0);
+    }
+
+    #[test]
+    fn nonzero_integers_serialize_as_their_value() {
+        let value = (core::num::NonZeroU32::new(7).unwrap(), core::num::NonZeroI8::new(-3).unwrap());
+        assert_eq!(record(&value), vec![entry("0", "u32: 7"), entry("1", "i8: -3")]);
+    }
+}
@@ -0,0 +1,150 @@
+use std::collections::HashSet;
+use serde_json::{json, Value};
+
+use super::schema::{identifier, DataType, Type, TypeSchema};
+
+/// Builds an Avro schema with the root type as the top-level record. Each named type is defined
+/// at its first use and referenced by name afterwards, as Avro requires.
+pub fn to_avro(schema: &TypeSchema) -> Value {
+    let mut defined = HashSet::new();
+    let hint = schema.root().term.clone().unwrap_or_else(|| "Root".to_string());
+    get_avro_type(schema, schema.root(), &identifier(&hint), &mut defined)
+}
+
+fn get_avro_record(schema: &TypeSchema, name: String, fields: &[&Type], defined: &mut HashSet<String>) -> Value {
+    let mut avro_fields = Vec::new();
+    for (index, field) in fields.iter().enumerate() {
+        let field_name = match &field.name {
+            Some(n) => identifier(n),
+            None => format!("_{}", index),
+        };
+        let hint = format!("{}_{}", name, field_name);
+        avro_fields.push(json!({ "name": field_name, "type": get_avro_type(schema, field, &hint, defined) }));
+    }
+    json!({ "type": "record", "name": name, "fields": avro_fields })
+}
+
+fn get_avro_type(schema: &TypeSchema, node: &Type, hint: &str, defined: &mut HashSet<String>) -> Value {
+    let children: Vec<&Type> = node.children(schema).collect();
+    match node.datatype {
+        DataType::Bool => json!("boolean"),
+        DataType::Int => match (node.signed, node.length) {
+            (Some(true), Some(1 | 2 | 4)) | (Some(false), Some(1 | 2)) => json!("int"),
+            (Some(true), Some(8)) | (Some(false), Some(4)) => json!("long"),
+            // Wider than a signed long: store the exact value as an unscaled decimal
+            (_, Some(8)) => json!({ "type": "bytes", "logicalType": "decimal", "precision": 20, "scale": 0 }),
+            _ => json!({ "type": "bytes", "logicalType": "decimal", "precision": 39, "scale": 0 }),
+        },
+        DataType::Float => match node.length {
+            Some(4) => json!("float"),
+            _ => json!("double"),
+        },
+        DataType::String => json!("string"),
+        DataType::Struct => {
+            let name = identifier(node.term.as_ref().unwrap());
+            if !defined.insert(name.clone()) {
+                return json!(name);
+            }
+            get_avro_record(schema, name, &children, defined)
+        },
+        DataType::Enum => {
+            let name = identifier(node.term.as_ref().unwrap());
+            if let Some(variant_names) = node.unit_variant_names(schema) {
+                if !defined.insert(name.clone()) {
+                    return json!(name);
+                }
+                let symbols: Vec<String> = variant_names.iter().map(|v| identifier(v)).collect();
+                return json!({ "type": "enum", "name": name, "symbols": symbols });
+            }
+            // Data-carrying enums become a union with one record per variant
+            let mut variants = Vec::new();
+            for variant in children.iter() {
+                match variant.datatype {
+                    DataType::Variant => {
+                        let variant_name = format!("{}{}", name, identifier(variant.name.as_ref().unwrap()));
+                        if !defined.insert(variant_name.clone()) {
+                            variants.push(json!(variant_name));
+                            continue;
+                        }
+                        let fields: Vec<&Type> = variant.children(schema).collect();
+                        variants.push(get_avro_record(schema, variant_name, &fields, defined));
+                    },
+                    _ => variants.push(get_avro_type(schema, variant, hint, defined)),
+                }
+            }
+            json!(variants)
+        },
+        DataType::Variant | DataType::Tuple => {
+            let name = format!("{}Tuple", hint);
+            if !defined.insert(name.clone()) {
+                return json!(name);
+            }
+            get_avro_record(schema, name, &children, defined)
+        },
+        DataType::Vec if node.is_byte_vec() => {
+            json!("bytes")
+        },
+        DataType::Vec | DataType::Array | DataType::HashSet => {
+            let items = get_avro_type(schema, children[0], &format!("{}_item", hint), defined);
+            json!({ "type": "array", "items": items })
+        },
+        DataType::HashMap => {
+            // Avro map keys are always strings
+            let values = match node.map_entry(schema) {
+                Some((_, value)) => get_avro_type(schema, value, &format!("{}_value", hint), defined),
+                None => json!("null"),
+            };
+            json!({ "type": "map", "values": values })
+        },
+        DataType::Option => match get_avro_type(schema, children[0], hint, defined) {
+            // Unions can't nest, so an inner union gains a null branch instead
+            Value::Array(mut branches) => {
+                if !branches.contains(&json!("null")) {
+                    branches.insert(0, json!("null"));
+                }
+                Value::Array(branches)
+            },
+            inner => json!(["null", inner]),
+        },
+        DataType::Result => {
+            let ok = get_avro_type(schema, children[0], &format!("{}_Ok", hint), defined);
+            let err = get_avro_type(schema, children[1], &format!("{}_Err", hint), defined);
+            json!({ "type": "record", "name": format!("{}Result", hint), "fields": [
+                { "name": "Ok", "type": ["null", ok], "default": null },
+                { "name": "Err", "type": ["null", err], "default": null },
+            ] })
+        },
+        DataType::Undefined => json!("null"),
+    }
+}
+
+#[cfg(test)]
+#[allow(dead_code)]
+mod tests {
+    use super::*;
+    use borsh_derive::BorshSchema;
+    use crate::serialize::schema::get_schema;
+
+    #[derive(BorshSchema)]
+    enum Shape { Circle(f64), Square { side: u32 } }
+
+    #[derive(BorshSchema)]
+    struct Other { label: String }
+
+    #[derive(BorshSchema)]
+    struct Person { name: String, age: u32, other: Option<Other>, shape: Option<Shape>, nested: Option<Option<u8>> }
+
+    #[test]
+    fn snapshot_for_sample() {
+        let other = json!({ "type": "record", "name": "Other", "fields": [{ "name": "label", "type": "string" }] });
+        let circle = json!({ "type": "record", "name": "ShapeCircle", "fields": [{ "name": "_0", "type": "double" }] });
+        let square = json!({ "type": "record", "name": "ShapeSquare", "fields": [{ "name": "side", "type": "long" }] });
+        assert_eq!(to_avro(&get_schema::<Person>()), json!({ "type": "record", "name": "Person", "fields": [
+            { "name": "name", "type": "string" },
+            { "name": "age", "type": "long" },
+            { "name": "other", "type": ["null", other] },
+            { "name": "shape", "type": ["null", circle, square] },
+            { "name": "nested", "type": ["null", "int"] },
+        ] }));
+    }
+}
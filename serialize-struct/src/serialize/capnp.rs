@@ -0,0 +1,227 @@
+use std::collections::HashSet;
+
+use super::schema::{DataType, Type, TypeSchema};
+
+/// Builds a Cap'n Proto schema file. Struct terms become structs with fields numbered in
+/// declaration order, unit-only enums become enums and data-carrying enums become structs holding
+/// an unnamed union. Tuples, map entries and nested options get generated helper structs.
+pub fn to_capnp(schema: &TypeSchema) -> String {
+    let mut writer = CapnpWriter { schema, aux: Vec::new(), aux_names: HashSet::new() };
+    let root_name = schema.root().term.clone().unwrap_or_else(|| "Root".to_string());
+    let mut out = format!("@{:#018x};\n", file_id(&root_name));
+    if schema.root().datatype == DataType::Struct && schema.root().fields.is_some() {
+        let fields: Vec<&Type> = schema.root().children(schema).collect();
+        out.push('\n');
+        out.push_str(&writer.struct_def(&type_name(&root_name), &fields));
+    }
+    // A recursive root is also a term and has already been written
+    for (term, t) in schema.terms().iter().filter(|(term, _)| Some(*term) != schema.root().term.as_ref()) {
+        let children: Vec<&Type> = t.children(schema).collect();
+        out.push('\n');
+        match t.datatype {
+            DataType::Enum => out.push_str(&writer.enum_def(&type_name(term), t, &children)),
+            _ => out.push_str(&writer.struct_def(&type_name(term), &children)),
+        }
+    }
+    for def in writer.aux.iter() {
+        out.push('\n');
+        out.push_str(def);
+    }
+    out
+}
+
+/// Cap'n Proto needs a unique 64-bit file ID with the top bit set; deriving it from the root
+/// name keeps the output stable across runs.
+fn file_id(name: &str) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in name.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash | (1 << 63)
+}
+
+/// Type names are UpperCamelCase without punctuation, so `Wrapper<string>` becomes `WrapperString`.
+fn type_name(name: &str) -> String {
+    name.split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let mut chars = part.chars();
+            chars.next().map(|c| c.to_ascii_uppercase().to_string() + chars.as_str()).unwrap_or_default()
+        })
+        .collect()
+}
+
+/// Field and enumerant names are lowerCamelCase and may not contain underscores.
+fn member_name(name: &str) -> String {
+    let upper = type_name(name);
+    let mut chars = upper.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_digit() => format!("f{}", upper),
+        Some(c) => c.to_ascii_lowercase().to_string() + chars.as_str(),
+        None => upper,
+    }
+}
+
+struct CapnpWriter<'a> {
+    schema: &'a TypeSchema,
+    aux: Vec<String>,
+    aux_names: HashSet<String>,
+}
+
+impl<'a> CapnpWriter<'a> {
+    fn struct_def(&mut self, name: &str, fields: &[&Type]) -> String {
+        let mut body = String::new();
+        let mut ordinal = 0;
+        for (index, field) in fields.iter().enumerate() {
+            let field_name = match &field.name {
+                Some(n) => member_name(n),
+                None => format!("f{}", index),
+            };
+            let hint = format!("{}{}", name, type_name(&field_name));
+            self.member(&mut body, &mut ordinal, &field_name, field, &hint);
+        }
+        format!("struct {} {{\n{}}}\n", name, body)
+    }
+
+    fn enum_def(&mut self, name: &str, node: &Type, variants: &[&Type]) -> String {
+        if let Some(variant_names) = node.unit_variant_names(self.schema) {
+            let mut body = String::new();
+            for (ordinal, variant) in variant_names.iter().enumerate() {
+                body.push_str(&format!("  {} @{};\n", member_name(variant), ordinal));
+            }
+            return format!("enum {} {{\n{}}}\n", name, body);
+        }
+        let mut body = String::new();
+        for (ordinal, variant) in variants.iter().enumerate() {
+            let variant_name = variant.name.clone().unwrap_or_default();
+            let hint = format!("{}{}", name, type_name(&variant_name));
+            let payload: Vec<&Type> = variant.children(self.schema).collect();
+            let payload_type = match (&variant.datatype, payload.len()) {
+                (DataType::Variant, 0) => "Void".to_string(),
+                (DataType::Variant, 1) => self.type_ref(payload[0], &hint),
+                (DataType::Variant, _) => self.aux_struct(&hint, &payload),
+                _ => self.type_ref(variant, &hint),
+            };
+            body.push_str(&format!("    {} @{} :{};\n", member_name(&variant_name), ordinal, payload_type));
+        }
+        format!("struct {} {{\n  union {{\n{}  }}\n}}\n", name, body)
+    }
+
+    /// Writes one field, expanding `Option` and `Result` into a named union group.
+    fn member(&mut self, body: &mut String, ordinal: &mut u32, name: &str, node: &Type, hint: &str) {
+        let children: Vec<&Type> = node.children(self.schema).collect();
+        let arms = match node.datatype {
+            DataType::Option => Some([("none", None), ("some", children.first().copied())]),
+            DataType::Result => Some([("ok", children.first().copied()), ("err", children.get(1).copied())]),
+            _ => None,
+        };
+        match arms {
+            Some(arms) => {
+                body.push_str(&format!("  {} :union {{\n", name));
+                for (arm, inner) in arms {
+                    let inner_type = match inner {
+                        Some(t) => self.type_ref(t, &format!("{}{}", hint, type_name(arm))),
+                        None => "Void".to_string(),
+                    };
+                    body.push_str(&format!("    {} @{} :{};\n", arm, ordinal, inner_type));
+                    *ordinal += 1;
+                }
+                body.push_str("  }\n");
+            },
+            None => {
+                let field_type = self.type_ref(node, hint);
+                body.push_str(&format!("  {} @{} :{};\n", name, ordinal, field_type));
+                *ordinal += 1;
+            },
+        }
+    }
+
+    fn type_ref(&mut self, node: &Type, hint: &str) -> String {
+        let children: Vec<&Type> = node.children(self.schema).collect();
+        match node.datatype {
+            DataType::Bool => "Bool".to_string(),
+            DataType::Int => match (node.signed, node.length) {
+                (Some(true), Some(l @ (1 | 2 | 4 | 8))) => format!("Int{}", l * 8),
+                (Some(false), Some(l @ (1 | 2 | 4 | 8))) => format!("UInt{}", l * 8),
+                // No 128-bit integers: keep the little-endian bytes
+                _ => "Data".to_string(),
+            },
+            DataType::Float => match node.length {
+                Some(4) => "Float32".to_string(),
+                _ => "Float64".to_string(),
+            },
+            DataType::String => "Text".to_string(),
+            DataType::Struct | DataType::Enum if node.fields.is_none() => type_name(node.term.as_ref().unwrap()),
+            DataType::Vec if node.is_byte_vec() => {
+                "Data".to_string()
+            },
+            DataType::Vec | DataType::Array | DataType::HashSet => {
+                format!("List({})", self.type_ref(children[0], &format!("{}Item", hint)))
+            },
+            DataType::HashMap => match node.map_entry(self.schema) {
+                Some((key, value)) => {
+                    let key = Type { name: Some("key".to_string()), ..key.clone() };
+                    let value = Type { name: Some("value".to_string()), ..value.clone() };
+                    format!("List({})", self.aux_struct(&format!("{}Entry", hint), &[&key, &value]))
+                },
+                None => "Void".to_string(),
+            },
+            DataType::Undefined => "Void".to_string(),
+            // Tuples, inline structs and options or results outside a field need a struct of their own
+            DataType::Option | DataType::Result => {
+                let value = Type { name: Some("value".to_string()), ..node.clone() };
+                self.aux_struct(hint, &[&value])
+            },
+            _ => self.aux_struct(hint, &children),
+        }
+    }
+
+    fn aux_struct(&mut self, name: &str, fields: &[&Type]) -> String {
+        if self.aux_names.insert(name.to_string()) {
+            let def = self.struct_def(name, fields);
+            self.aux.push(def);
+        }
+        name.to_string()
+    }
+}
+
+#[cfg(test)]
+#[allow(dead_code)]
+mod tests {
+    use super::*;
+    use borsh_derive::BorshSchema;
+    use crate::serialize::schema::{get_schema, tests::linked_list_container};
+
+    #[derive(BorshSchema)]
+    enum Mood { Happy, Sad }
+
+    #[derive(BorshSchema)]
+    struct Other { label: String }
+
+    #[derive(BorshSchema)]
+    struct Person { name: String, age: u32, mood: Mood, thing: Vec<(u8, Other)>, nickname: Option<String> }
+
+    #[test]
+    fn snapshot_for_sample() {
+        let expected = [
+            "@0xc0d76f1f51639ec0;\n",
+            "\nstruct Person {\n  name @0 :Text;\n  age @1 :UInt32;\n  mood @2 :Mood;\n  thing @3 :List(PersonThingItem);\n",
+            "  nickname :union {\n    none @4 :Void;\n    some @5 :Text;\n  }\n}\n",
+            "\nenum Mood {\n  happy @0;\n  sad @1;\n}\n",
+            "\nstruct Other {\n  label @0 :Text;\n}\n",
+            "\nstruct PersonThingItem {\n  f0 @0 :UInt8;\n  f1 @1 :Other;\n}\n",
+        ];
+        assert_eq!(to_capnp(&get_schema::<Person>()), expected.concat());
+    }
+
+    #[test]
+    fn recursive_root_is_declared_once() {
+        let tsch = TypeSchema::from_container(&linked_list_container()).unwrap();
+        let expected = [
+            "@0xbd249bb480032121;\n",
+            "\nstruct List {\n  value @0 :UInt32;\n  next :union {\n    none @1 :Void;\n    some @2 :List;\n  }\n}\n",
+        ];
+        assert_eq!(to_capnp(&tsch), expected.concat());
+    }
+}
@@ -0,0 +1,134 @@
+use super::schema::{DataType, Type, TypeSchema};
+
+/// Dotted column names for every leaf value, for loading schema-shaped data into flat tables.
+/// Repeated values are marked with `[]`, map entries split into `key` and `value`, `Option` is
+/// looked through and an enum gets a column for its variant name next to its payload columns.
+/// Paths stop once they reach `max_depth` segments, which bounds recursive types.
+pub fn to_flat_columns(schema: &TypeSchema, max_depth: usize) -> Vec<String> {
+    let mut columns = Vec::new();
+    let root = schema.root();
+    match root.datatype {
+        DataType::Struct => {
+            for field in root.children(schema) {
+                let name = field.name.clone().unwrap_or_default();
+                add_columns(schema, field, name, 1, max_depth, &mut columns);
+            }
+        },
+        _ => add_columns(schema, root, root.name.clone().unwrap_or_default(), 1, max_depth, &mut columns),
+    }
+    columns
+}
+
+fn add_columns(schema: &TypeSchema, node: &Type, path: String, depth: usize, max_depth: usize, columns: &mut Vec<String>) {
+    if depth > max_depth {
+        return;
+    }
+    let children: Vec<&Type> = node.children(schema).collect();
+    // A hand-built container may have no children, which leaves nothing to list
+    let first = children.first().copied();
+    match node.datatype {
+        DataType::Bool | DataType::Int | DataType::Float | DataType::String | DataType::Undefined => columns.push(path),
+        DataType::Option => {
+            if let Some(inner) = first {
+                add_columns(schema, inner, path, depth, max_depth, columns);
+            }
+        },
+        DataType::Vec if node.is_byte_vec() => {
+            columns.push(path)
+        },
+        DataType::Vec | DataType::Array | DataType::HashSet => {
+            if let Some(element) = first {
+                add_columns(schema, element, format!("{}[]", path), depth, max_depth, columns);
+            }
+        },
+        DataType::HashMap => {
+            if let Some((key, value)) = node.map_entry(schema) {
+                add_columns(schema, key, format!("{}[].key", path), depth + 1, max_depth, columns);
+                add_columns(schema, value, format!("{}[].value", path), depth + 1, max_depth, columns);
+            }
+        },
+        DataType::Result => {
+            if let [ok, err] = children[..] {
+                add_columns(schema, ok, format!("{}.Ok", path), depth + 1, max_depth, columns);
+                add_columns(schema, err, format!("{}.Err", path), depth + 1, max_depth, columns);
+            }
+        },
+        DataType::Enum => {
+            columns.push(path.clone());
+            for variant in children {
+                let variant_path = format!("{}.{}", path, variant.name.clone().unwrap_or_default());
+                match variant.datatype {
+                    DataType::Variant => {
+                        for (index, field) in variant.children(schema).enumerate() {
+                            add_columns(schema, field, format!("{}.{}", variant_path, index), depth + 2, max_depth, columns);
+                        }
+                    },
+                    _ => add_columns(schema, variant, variant_path, depth + 1, max_depth, columns),
+                }
+            }
+        },
+        DataType::Struct | DataType::Tuple | DataType::Variant => {
+            for (index, field) in children.into_iter().enumerate() {
+                let name = field.name.clone().unwrap_or_else(|| index.to_string());
+                add_columns(schema, field, format!("{}.{}", path, name), depth + 1, max_depth, columns);
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serialize::schema::get_schema;
+    use crate::serialize::schema::tests::linked_list_container;
+    use borsh_derive::BorshSchema;
+
+    #[derive(BorshSchema)]
+    #[allow(dead_code)]
+    enum Something { A, B }
+
+    #[derive(BorshSchema)]
+    #[allow(dead_code)]
+    struct Other { label: String, cool: bool, some: Something }
+
+    #[derive(BorshSchema)]
+    #[allow(dead_code)]
+    struct Person { name: String, uuid: u128, thing: Other, vector: Vec<(u128, u64, String)>, others: Vec<Other>, bytes: Vec<u8> }
+
+    #[test]
+    fn columns_for_sample() {
+        assert_eq!(to_flat_columns(&get_schema::<Person>(), 8), vec![
+            "name",
+            "uuid",
+            "thing.label",
+            "thing.cool",
+            "thing.some",
+            "vector[].0",
+            "vector[].1",
+            "vector[].2",
+            "others[].label",
+            "others[].cool",
+            "others[].some",
+            "bytes",
+        ]);
+    }
+
+    #[test]
+    fn depth_bounds_recursive_types() {
+        let tsch = TypeSchema::from_container(&linked_list_container()).unwrap();
+        assert_eq!(to_flat_columns(&tsch, 1), vec!["value"]);
+        assert_eq!(to_flat_columns(&tsch, 3), vec!["value", "next.value", "next.next.value"]);
+    }
+
+    #[test]
+    fn empty_containers_have_no_columns() {
+        let empty = |datatype: DataType, name: &str| Type { datatype, name: Some(name.to_owned()), fields: Some(Vec::new()), ..Type::default() };
+        let root = Type {
+            datatype: DataType::Struct,
+            term: Some("Broken".to_owned()),
+            fields: Some(vec![empty(DataType::Vec, "list"), empty(DataType::Option, "maybe"), empty(DataType::HashMap, "map")]),
+            ..Type::default()
+        };
+        assert!(to_flat_columns(&TypeSchema::from_parts(root, Default::default()), 4).is_empty());
+    }
+}
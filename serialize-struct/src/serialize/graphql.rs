@@ -0,0 +1,161 @@
+use borsh::maybestd::collections::{BTreeMap, HashSet};
+use graphql_parser::schema::{self as gql, Definition, TypeDefinition};
+
+use super::schema::{DataType, SchemaError, Type, TypeSchema};
+
+/// An object or input type's name with the name and type of each of its fields.
+type SdlObject<'a> = (&'a str, Vec<(&'a str, &'a gql::Type<'a, String>)>);
+
+/// Builds a schema from GraphQL SDL. The first object type is the root; other object and input
+/// types become struct terms and enums become unit-variant enum terms. Nullable fields are wrapped
+/// in `Option`, lists become `Vec`, and `Int` is taken as a signed 32-bit integer.
+pub fn from_graphql_sdl(sdl: &str) -> Result<TypeSchema, SchemaError> {
+    let document = gql::parse_schema::<String>(sdl).map_err(|e| SchemaError::Parse(e.to_string()))?;
+    let mut objects: Vec<SdlObject> = Vec::new();
+    let mut enums: Vec<(&str, Vec<&str>)> = Vec::new();
+    for definition in document.definitions.iter() {
+        match definition {
+            Definition::TypeDefinition(TypeDefinition::Object(o)) => {
+                objects.push((&o.name, o.fields.iter().map(|f| (f.name.as_str(), &f.field_type)).collect()));
+            },
+            Definition::TypeDefinition(TypeDefinition::InputObject(o)) => {
+                objects.push((&o.name, o.fields.iter().map(|f| (f.name.as_str(), &f.value_type)).collect()));
+            },
+            Definition::TypeDefinition(TypeDefinition::Enum(e)) => {
+                enums.push((&e.name, e.values.iter().map(|v| v.name.as_str()).collect()));
+            },
+            _ => {},
+        }
+    }
+    let object_names: HashSet<&str> = objects.iter().map(|(name, _)| *name).collect();
+    let enum_names: HashSet<&str> = enums.iter().map(|(name, _)| *name).collect();
+
+    let mut terms = BTreeMap::new();
+    for (name, values) in enums.iter() {
+        let variants: Vec<Type> = values.iter()
+            .map(|v| Type { datatype: DataType::Variant, name: Some(v.to_string()), ..Type::default() })
+            .collect();
+        terms.insert(name.to_string(), Type {
+            datatype: DataType::Enum, length: Some(variants.len() as u32), fields: Some(variants), term: Some(name.to_string()), ..Type::default()
+        });
+    }
+    let mut root = None;
+    for (index, (name, fields)) in objects.iter().enumerate() {
+        let mut fs = Vec::new();
+        for (field_name, field_type) in fields {
+            let mut field = get_graphql_type(field_type, false, &object_names, &enum_names)?;
+            field.name = Some(field_name.to_string());
+            fs.push(field);
+        }
+        let t = Type { datatype: DataType::Struct, fields: Some(fs), term: Some(name.to_string()), ..Type::default() };
+        if index == 0 {
+            root = Some(Type { name: Some(name.to_string()), ..t.clone() });
+            // The root is only kept as a term when something refers back to it
+            if !sdl_references(&objects, name) {
+                continue;
+            }
+        }
+        terms.insert(name.to_string(), t);
+    }
+    let root = root.ok_or_else(|| SchemaError::Parse("No object type to use as the root".to_owned()))?;
+    Ok(TypeSchema::from_parts(root, terms))
+}
+
+fn sdl_references(objects: &[SdlObject], term: &str) -> bool {
+    fn named<'a>(t: &'a gql::Type<String>) -> &'a str {
+        match t {
+            gql::Type::NamedType(n) => n,
+            gql::Type::ListType(inner) | gql::Type::NonNullType(inner) => named(inner),
+        }
+    }
+    objects.iter().flat_map(|(_, fields)| fields.iter()).any(|(_, t)| named(t) == term)
+}
+
+fn get_graphql_type(field_type: &gql::Type<String>, non_null: bool, objects: &HashSet<&str>, enums: &HashSet<&str>) -> Result<Type, SchemaError> {
+    let t = match field_type {
+        gql::Type::NonNullType(inner) => return get_graphql_type(inner, true, objects, enums),
+        gql::Type::ListType(inner) => {
+            let items = get_graphql_type(inner, false, objects, enums)?;
+            Type { datatype: DataType::Vec, fields: Some(vec![items]), ..Type::default() }
+        },
+        gql::Type::NamedType(name) => match name.as_str() {
+            "Int" => Type { datatype: DataType::Int, signed: Some(true), length: Some(4), ..Type::default() },
+            "Float" => Type { datatype: DataType::Float, length: Some(8), ..Type::default() },
+            "String" | "ID" => Type { datatype: DataType::String, ..Type::default() },
+            "Boolean" => Type { datatype: DataType::Bool, ..Type::default() },
+            n if objects.contains(n) => Type { datatype: DataType::Struct, term: Some(n.to_owned()), ..Type::default() },
+            n if enums.contains(n) => Type { datatype: DataType::Enum, term: Some(n.to_owned()), ..Type::default() },
+            n => return Err(SchemaError::UnknownDeclaration(n.to_owned())),
+        },
+    };
+    if non_null {
+        Ok(t)
+    } else {
+        Ok(Type { datatype: DataType::Option, fields: Some(vec![t]), ..Type::default() })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SDL: &str = r#"
+        type Person {
+            name: String!
+            nickname: String
+            tags: [String!]!
+            role: Role!
+            manager: Person
+        }
+        type Address { street: String! }
+        enum Role { ADMIN MEMBER }
+    "#;
+
+    fn field<'a>(tsch: &'a TypeSchema, path: &str) -> &'a Type {
+        tsch.field_type(path).unwrap()
+    }
+
+    #[test]
+    fn first_object_is_the_root() {
+        let tsch = from_graphql_sdl(SDL).unwrap();
+        assert_eq!(tsch.root().term.as_deref(), Some("Person"));
+        assert_eq!(tsch.root().fields.as_ref().map(Vec::len), Some(5));
+        // `Person` refers to itself, so it is also kept as a term
+        assert_eq!(tsch.terms().keys().collect::<Vec<_>>(), vec!["Address", "Person", "Role"]);
+        let tsch = from_graphql_sdl("type Address { street: String! }").unwrap();
+        assert!(tsch.terms().is_empty());
+    }
+
+    #[test]
+    fn maps_nullability_lists_and_enums() {
+        let tsch = from_graphql_sdl(SDL).unwrap();
+        assert_eq!(field(&tsch, "name").datatype, DataType::String);
+        let nickname = tsch.root().fields.as_ref().unwrap().iter().find(|f| f.name.as_deref() == Some("nickname")).unwrap();
+        assert_eq!(nickname.datatype, DataType::Option);
+        assert_eq!(nickname.fields.as_ref().unwrap()[0].datatype, DataType::String);
+        assert_eq!(field(&tsch, "tags").datatype, DataType::Vec);
+        assert_eq!(field(&tsch, "tags.*").datatype, DataType::String);
+        let role = field(&tsch, "role");
+        assert_eq!((role.datatype.clone(), role.term.as_deref()), (DataType::Enum, Some("Role")));
+        let variants: Vec<&str> = tsch.terms()["Role"].fields.iter().flatten().filter_map(|v| v.name.as_deref()).collect();
+        assert_eq!(variants, vec!["ADMIN", "MEMBER"]);
+    }
+
+    #[test]
+    fn self_reference_resolves_to_the_root_term() {
+        let tsch = from_graphql_sdl(SDL).unwrap();
+        let manager = field(&tsch, "manager");
+        assert_eq!(manager.datatype, DataType::Option);
+        let manager = &manager.fields.as_ref().unwrap()[0];
+        assert_eq!((manager.datatype.clone(), manager.term.as_deref()), (DataType::Struct, Some("Person")));
+        assert_eq!(field(&tsch, "manager.name").datatype, DataType::String);
+        assert_eq!(tsch.validate(), Ok(()));
+    }
+
+    #[test]
+    fn custom_scalars_are_unknown_declarations() {
+        let result = from_graphql_sdl("scalar Date\ntype Event { at: Date! }");
+        assert_eq!(result, Err(SchemaError::UnknownDeclaration("Date".to_owned())));
+        assert!(matches!(from_graphql_sdl("type {"), Err(SchemaError::Parse(_))));
+    }
+}
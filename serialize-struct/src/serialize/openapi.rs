@@ -0,0 +1,168 @@
+use serde_json::{json, Map, Value};
+
+use super::schema::{DataType, Type, TypeSchema};
+
+const COMPONENTS_REF: &str = "#/components/schemas/";
+
+/// Builds an OpenAPI 3.0 `components.schemas` fragment for the root type and every named term.
+pub fn to_openapi_components(schema: &TypeSchema) -> Value {
+    let mut schemas = Map::new();
+    if let Some(term) = &schema.root().term {
+        schemas.insert(term.clone(), get_openapi_type(schema, schema.root()));
+    }
+    for (term, t) in schema.terms().iter() {
+        schemas.insert(term.clone(), get_openapi_type(schema, t));
+    }
+    json!({ "components": { "schemas": Value::Object(schemas) } })
+}
+
+fn get_openapi_field(schema: &TypeSchema, field: &Type) -> Value {
+    match field.datatype {
+        DataType::Struct | DataType::Enum if field.fields.is_none() => {
+            json!({ "$ref": format!("{}{}", COMPONENTS_REF, field.term.as_ref().unwrap()) })
+        },
+        _ => get_openapi_type(schema, field),
+    }
+}
+
+fn get_openapi_items(schema: &TypeSchema, fields: &[Type]) -> Value {
+    match fields.len() {
+        1 => get_openapi_field(schema, &fields[0]),
+        _ => json!({ "oneOf": fields.iter().map(|f| get_openapi_field(schema, f)).collect::<Vec<Value>>() }),
+    }
+}
+
+fn get_openapi_type(schema: &TypeSchema, node: &Type) -> Value {
+    let fields: &[Type] = match &node.fields {
+        Some(f) => f,
+        None => &[],
+    };
+    match node.datatype {
+        DataType::Bool => json!({ "type": "boolean" }),
+        DataType::Int => {
+            let mut int = match node.length {
+                Some(4) => json!({ "type": "integer", "format": "int32" }),
+                Some(8) => json!({ "type": "integer", "format": "int64" }),
+                _ => json!({ "type": "integer" }),
+            };
+            // 128-bit bounds can't be represented as JSON numbers, so they are left open
+            if let (Some((min, max)), Some(1 | 2 | 4 | 8)) = (node.int_range(), node.length) {
+                int["minimum"] = json!(min as i64);
+                int["maximum"] = json!(max as u64);
+            }
+            int
+        },
+        DataType::Float => match node.length {
+            Some(4) => json!({ "type": "number", "format": "float" }),
+            _ => json!({ "type": "number", "format": "double" }),
+        },
+        DataType::String => json!({ "type": "string" }),
+        DataType::Struct => {
+            let mut properties = Map::new();
+            let mut required = Vec::new();
+            for field in fields {
+                let name = field.name.clone().unwrap_or_default();
+                if field.datatype != DataType::Option {
+                    required.push(Value::String(name.clone()));
+                }
+                properties.insert(name, get_openapi_field(schema, field));
+            }
+            let mut object = json!({ "type": "object", "properties": Value::Object(properties) });
+            if !required.is_empty() {
+                object["required"] = Value::Array(required);
+            }
+            object
+        },
+        DataType::Enum => {
+            if let Some(variant_names) = node.unit_variant_names(schema) {
+                return json!({ "type": "string", "enum": variant_names });
+            }
+            let mut variants = Vec::new();
+            for variant in fields {
+                let name = variant.name.clone().unwrap_or_default();
+                let unit = match variant.datatype {
+                    DataType::Variant => variant.fields.as_ref().is_none_or(|f| f.is_empty()),
+                    _ => false,
+                };
+                if unit {
+                    variants.push(json!({ "type": "string", "enum": [name] }));
+                } else {
+                    variants.push(json!({
+                        "type": "object",
+                        "properties": { name.clone(): get_openapi_field(schema, variant) },
+                        "required": [name],
+                    }));
+                }
+            }
+            json!({ "oneOf": variants })
+        },
+        DataType::Variant | DataType::Tuple => match fields.len() {
+            0 => json!({ "type": "array", "maxItems": 0 }),
+            1 if node.datatype == DataType::Variant => get_openapi_field(schema, &fields[0]),
+            len => json!({ "type": "array", "items": get_openapi_items(schema, fields), "minItems": len, "maxItems": len }),
+        },
+        DataType::Array => {
+            let len = node.length.unwrap_or_default();
+            json!({ "type": "array", "items": get_openapi_items(schema, fields), "minItems": len, "maxItems": len })
+        },
+        DataType::Vec => json!({ "type": "array", "items": get_openapi_items(schema, fields) }),
+        DataType::HashSet => json!({ "type": "array", "items": get_openapi_items(schema, fields), "uniqueItems": true }),
+        DataType::HashMap => {
+            // OpenAPI map keys are always strings
+            let value = match node.map_entry(schema) {
+                Some((_, value)) => get_openapi_field(schema, value),
+                None => json!({}),
+            };
+            json!({ "type": "object", "additionalProperties": value })
+        },
+        DataType::Option => {
+            // OpenAPI 3.0 ignores siblings of `$ref`, so a nullable reference is wrapped in `allOf`.
+            let mut inner = get_openapi_items(schema, fields);
+            if inner.get("$ref").is_some() {
+                inner = json!({ "allOf": [inner] });
+            }
+            inner["nullable"] = Value::Bool(true);
+            inner
+        },
+        DataType::Result => {
+            let ok = fields.first().map_or(json!({}), |f| get_openapi_field(schema, f));
+            let err = fields.get(1).map_or(json!({}), |f| get_openapi_field(schema, f));
+            json!({ "oneOf": [
+                { "type": "object", "properties": { "Ok": ok }, "required": ["Ok"] },
+                { "type": "object", "properties": { "Err": err }, "required": ["Err"] },
+            ] })
+        },
+        DataType::Undefined => json!({}),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use borsh::maybestd::collections::BTreeMap;
+
+    fn root_with(field: Type) -> TypeSchema {
+        let root = Type { datatype: DataType::Struct, name: Some("Root".to_owned()), term: Some("Root".to_owned()), fields: Some(vec![field]), ..Type::default() };
+        TypeSchema::from_parts(root, BTreeMap::new())
+    }
+
+    fn int(signed: bool, length: u32) -> Type {
+        Type { datatype: DataType::Int, name: Some("n".to_owned()), signed: Some(signed), length: Some(length), ..Type::default() }
+    }
+
+    #[test]
+    fn integer_bounds_follow_width_and_sign() {
+        let components = to_openapi_components(&root_with(int(true, 2)));
+        assert_eq!(components["components"]["schemas"]["Root"]["properties"]["n"], json!({ "type": "integer", "minimum": -32768, "maximum": 32767 }));
+        let components = to_openapi_components(&root_with(int(false, 1)));
+        assert_eq!(components["components"]["schemas"]["Root"]["properties"]["n"], json!({ "type": "integer", "minimum": 0, "maximum": 255 }));
+    }
+
+    #[test]
+    fn invalid_integer_width_has_no_bounds() {
+        let components = to_openapi_components(&root_with(int(false, 0)));
+        assert_eq!(components["components"]["schemas"]["Root"]["properties"]["n"], json!({ "type": "integer" }));
+        let components = to_openapi_components(&root_with(int(false, 17)));
+        assert_eq!(components["components"]["schemas"]["Root"]["properties"]["n"], json!({ "type": "integer" }));
+    }
+}
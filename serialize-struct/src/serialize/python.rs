@@ -0,0 +1,174 @@
+use std::collections::HashSet;
+
+use super::schema::{identifier, DataType, Type, TypeSchema};
+
+/// Builds a Python module of `@dataclass` definitions. Terms are declared before the types that
+/// use them, unit-only enums become `enum.Enum` classes and data-carrying enums become a `Union`
+/// of one dataclass per variant.
+pub fn to_python(schema: &TypeSchema) -> String {
+    let mut writer = PythonWriter { schema, defined: HashSet::new(), out: String::new() };
+    writer.out.push_str("from __future__ import annotations\n\nimport enum\nfrom dataclasses import dataclass\nfrom typing import Optional, Union\n");
+    let root = schema.root();
+    match (&root.datatype, &root.fields) {
+        (DataType::Struct, Some(_)) => {
+            let name = identifier(root.term.as_deref().unwrap_or("Root"));
+            writer.defined.insert(name.clone());
+            writer.dependencies(root);
+            let fields: Vec<&Type> = root.children(schema).collect();
+            writer.dataclass(&name, &fields);
+        },
+        _ => writer.dependencies(root),
+    }
+    // Terms that are not reachable from the root, e.g. after a merge
+    for term in schema.terms().keys() {
+        writer.term(term);
+    }
+    writer.out
+}
+
+struct PythonWriter<'a> {
+    schema: &'a TypeSchema,
+    defined: HashSet<String>,
+    out: String,
+}
+
+impl<'a> PythonWriter<'a> {
+    /// Declares every term referenced below `node` that hasn't been declared yet.
+    fn dependencies(&mut self, node: &Type) {
+        match (&node.datatype, &node.fields, &node.term) {
+            (DataType::Struct | DataType::Enum, None, Some(term)) => self.term(term),
+            _ => {
+                for child in node.fields.iter().flatten() {
+                    self.dependencies(child);
+                }
+            },
+        }
+    }
+
+    fn term(&mut self, term: &str) {
+        let name = identifier(term);
+        if !self.defined.insert(name.clone()) {
+            return;
+        }
+        let t = match self.schema.terms().get(term) {
+            Some(t) => t,
+            None => return,
+        };
+        self.dependencies(&Type { term: None, ..t.clone() });
+        let children: Vec<&Type> = t.children(self.schema).collect();
+        match t.datatype {
+            DataType::Enum => self.enumeration(&name, t, &children),
+            _ => self.dataclass(&name, &children),
+        }
+    }
+
+    fn dataclass(&mut self, name: &str, fields: &[&Type]) {
+        let mut body = String::new();
+        for (index, field) in fields.iter().enumerate() {
+            let field_name = match &field.name {
+                Some(n) => identifier(n),
+                None => format!("_{}", index),
+            };
+            body.push_str(&format!("    {}: {}\n", field_name, self.type_hint(field)));
+        }
+        if body.is_empty() {
+            body.push_str("    pass\n");
+        }
+        self.out.push_str(&format!("\n\n@dataclass\nclass {}:\n{}", name, body));
+    }
+
+    fn enumeration(&mut self, name: &str, node: &Type, variants: &[&Type]) {
+        if let Some(variant_names) = node.unit_variant_names(self.schema) {
+            let mut body = String::new();
+            for (index, variant) in variant_names.iter().enumerate() {
+                body.push_str(&format!("    {} = {}\n", identifier(variant), index));
+            }
+            self.out.push_str(&format!("\n\nclass {}(enum.Enum):\n{}", name, body));
+            return;
+        }
+        let mut members = Vec::new();
+        for variant in variants.iter() {
+            let variant_name = format!("{}{}", name, identifier(variant.name.as_deref().unwrap_or_default()));
+            match variant.datatype {
+                DataType::Variant => {
+                    if self.defined.insert(variant_name.clone()) {
+                        let fields: Vec<&Type> = variant.children(self.schema).collect();
+                        self.dataclass(&variant_name, &fields);
+                    }
+                    members.push(variant_name);
+                },
+                _ => members.push(self.type_hint(variant)),
+            }
+        }
+        self.out.push_str(&format!("\n\n{} = Union[{}]\n", name, members.join(", ")));
+    }
+
+    fn type_hint(&self, node: &Type) -> String {
+        // Struct and enum references are named rather than descended into, so recursive terms end
+        let children = || node.children(self.schema).collect::<Vec<&Type>>();
+        let hints = || children().into_iter().map(|c| self.type_hint(c)).collect::<Vec<String>>();
+        match node.datatype {
+            DataType::Bool => "bool".to_string(),
+            DataType::Int => "int".to_string(),
+            DataType::Float => "float".to_string(),
+            DataType::String => "str".to_string(),
+            DataType::Struct | DataType::Enum => identifier(node.term.as_deref().unwrap_or_default()),
+            DataType::Vec if node.is_byte_vec() => {
+                "bytes".to_string()
+            },
+            DataType::Vec | DataType::Array => format!("list[{}]", hints()[0]),
+            DataType::HashSet => format!("set[{}]", hints()[0]),
+            DataType::HashMap => match node.map_entry(self.schema) {
+                Some((key, value)) => format!("dict[{}, {}]", self.type_hint(key), self.type_hint(value)),
+                None => "dict".to_string(),
+            },
+            DataType::Option => format!("Optional[{}]", hints()[0]),
+            DataType::Result => format!("Union[{}]", hints().join(", ")),
+            DataType::Tuple | DataType::Variant => format!("tuple[{}]", hints().join(", ")),
+            DataType::Undefined => "None".to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(dead_code)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use borsh_derive::BorshSchema;
+    use crate::serialize::schema::{get_schema, tests::linked_list_container};
+
+    const HEADER: &str = "from __future__ import annotations\n\nimport enum\nfrom dataclasses import dataclass\nfrom typing import Optional, Union\n";
+
+    #[derive(BorshSchema)]
+    enum Mood { Happy, Sad }
+
+    #[derive(BorshSchema)]
+    enum Shape { Circle(f64), Square { side: u32 } }
+
+    #[derive(BorshSchema)]
+    struct Other { label: String }
+
+    #[derive(BorshSchema)]
+    struct Person { name: String, mood: Mood, shape: Shape, other: Option<Other>, data: Vec<u8>, scores: HashMap<String, u16> }
+
+    #[test]
+    fn declares_terms_before_the_root_dataclass() {
+        let expected = [
+            "\n\nclass Mood(enum.Enum):\n    Happy = 0\n    Sad = 1\n",
+            "\n\n@dataclass\nclass ShapeSquare:\n    side: int\n",
+            "\n\n@dataclass\nclass ShapeCircle:\n    _0: float\n",
+            "\n\nShape = Union[ShapeCircle, ShapeSquare]\n",
+            "\n\n@dataclass\nclass Other:\n    label: str\n",
+            "\n\n@dataclass\nclass Person:\n    name: str\n    mood: Mood\n    shape: Shape\n    other: Optional[Other]\n    data: bytes\n    scores: dict[str, int]\n",
+        ];
+        assert_eq!(to_python(&get_schema::<Person>()), HEADER.to_owned() + &expected.concat());
+    }
+
+    #[test]
+    fn recursive_dataclass_refers_to_itself() {
+        let tsch = TypeSchema::from_container(&linked_list_container()).unwrap();
+        let expected = "\n\n@dataclass\nclass List:\n    value: int\n    next: Optional[List]\n";
+        assert_eq!(to_python(&tsch), HEADER.to_owned() + expected);
+    }
+}
@@ -0,0 +1,96 @@
+use borsh::maybestd::collections::HashMap;
+use borsh::schema::{BorshSchema as BorshSchemaTrait, BorshSchemaContainer};
+
+use super::schema::{get_schema, SchemaError, TypeSchema};
+
+/// Schemas for many types, keyed by the Borsh declaration of their root.
+#[derive(Debug, Default)]
+pub struct SchemaRegistry {
+    by_name: HashMap<String, TypeSchema>,
+}
+
+impl SchemaRegistry {
+    pub fn new() -> Self {
+        SchemaRegistry::default()
+    }
+
+    pub fn register<T: BorshSchemaTrait>(&mut self) {
+        self.by_name.insert(T::declaration(), get_schema::<T>());
+    }
+
+    pub fn register_container(&mut self, container: &BorshSchemaContainer) -> Result<(), SchemaError> {
+        let schema = TypeSchema::from_container(container)?;
+        self.by_name.insert(container.declaration.clone(), schema);
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&TypeSchema> {
+        self.by_name.get(name)
+    }
+}
+
+#[cfg(test)]
+#[allow(dead_code)]
+mod tests {
+    use super::*;
+    use borsh::schema::{Definition, Fields};
+    use borsh_derive::BorshSchema;
+
+    #[derive(BorshSchema)]
+    struct Other { label: String }
+
+    #[derive(BorshSchema)]
+    struct Person { name: String, thing: Other }
+
+    #[derive(BorshSchema)]
+    struct Wrapper<T> { value: T }
+
+    #[test]
+    fn looks_up_by_declaration() {
+        let mut registry = SchemaRegistry::new();
+        registry.register::<Person>();
+        registry.register::<Wrapper<u8>>();
+        assert_eq!(registry.get("Person"), Some(&get_schema::<Person>()));
+        assert_eq!(registry.get("Wrapper<u8>"), Some(&get_schema::<Wrapper<u8>>()));
+        assert_eq!(registry.get("Other"), None);
+    }
+
+    #[test]
+    fn registering_twice_keeps_one_schema() {
+        let mut registry = SchemaRegistry::new();
+        registry.register::<Person>();
+        registry.register::<Person>();
+        assert_eq!(registry.by_name.len(), 1);
+        assert_eq!(registry.get("Person"), Some(&get_schema::<Person>()));
+    }
+
+    #[test]
+    fn containers_with_overlapping_terms_stay_separate() {
+        let mut registry = SchemaRegistry::new();
+        registry.register::<Person>();
+        let mut definitions = HashMap::new();
+        definitions.insert("Team".to_owned(), Definition::Struct {
+            fields: Fields::NamedFields(vec![("lead".to_owned(), "Other".to_owned())]),
+        });
+        definitions.insert("Other".to_owned(), Definition::Struct {
+            fields: Fields::NamedFields(vec![("label".to_owned(), "string".to_owned())]),
+        });
+        registry.register_container(&BorshSchemaContainer { declaration: "Team".to_owned(), definitions }).unwrap();
+        let team = registry.get("Team").unwrap();
+        let person = registry.get("Person").unwrap();
+        assert_eq!(team.terms()["Other"], person.terms()["Other"]);
+        assert_eq!(person, &get_schema::<Person>());
+    }
+
+    #[test]
+    fn rejects_containers_with_unknown_declarations() {
+        let mut registry = SchemaRegistry::new();
+        let mut definitions = HashMap::new();
+        definitions.insert("Team".to_owned(), Definition::Struct {
+            fields: Fields::NamedFields(vec![("lead".to_owned(), "Missing".to_owned())]),
+        });
+        let result = registry.register_container(&BorshSchemaContainer { declaration: "Team".to_owned(), definitions });
+        assert_eq!(result, Err(SchemaError::UnknownDeclaration("Missing".to_owned())));
+        assert_eq!(registry.get("Team"), None);
+    }
+}
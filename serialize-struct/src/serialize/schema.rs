@@ -1,10 +1,52 @@
 use core::marker::PhantomData;
+use std::sync::OnceLock;
 use regex::Regex;
-use borsh::maybestd::collections::HashMap;
+use borsh::maybestd::collections::BTreeMap;
+use borsh::maybestd::io::{Error, ErrorKind, Read, Result, Write};
+use borsh::{BorshDeserialize, BorshSerialize};
 use borsh::schema::{BorshSchema as BorshSchemaTrait, BorshSchemaContainer, Definition, Fields};
 use serde_derive::{Serialize};
 
-#[derive(Debug, Clone, PartialEq, Serialize)]
+/// Compiles a regex once and reuses it on every later call.
+macro_rules! regex {
+    ($re:literal) => {{
+        static RE: OnceLock<Regex> = OnceLock::new();
+        RE.get_or_init(|| Regex::new($re).unwrap())
+    }};
+}
+
+/// Builds a `TypeSchema` from struct definitions written inline, for schemas with no Rust type of
+/// their own. The first struct is the root and any following ones can be referenced by its fields:
+///
+/// ```
+/// let tsch = dynamic_struct::schema! {
+///     struct Person { name: String, home: Address }
+///     struct Address { street: String, number: u16 }
+/// };
+/// assert_eq!(tsch.root().term.as_deref(), Some("Person"));
+/// assert_eq!(tsch.terms().keys().collect::<Vec<_>>(), vec!["Address"]);
+/// ```
+#[macro_export]
+macro_rules! schema {
+    (
+        struct $root:ident { $($field:ident : $ty:ty),* $(,)? }
+        $(struct $name:ident { $($nfield:ident : $nty:ty),* $(,)? })*
+    ) => {{
+        // The derive refers to `borsh::...`, which this brings into scope
+        use $crate::__private::borsh;
+        #[derive($crate::__private::borsh_derive::BorshSchema)]
+        #[allow(dead_code)]
+        struct $root { $($field: $ty),* }
+        $(
+            #[derive($crate::__private::borsh_derive::BorshSchema)]
+            #[allow(dead_code)]
+            struct $name { $($nfield: $nty),* }
+        )*
+        $crate::serialize::schema::get_schema::<$root>()
+    }};
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, BorshSerialize, BorshDeserialize)]
 #[repr(u8)]
 pub enum DataType {
     Bool,
@@ -24,6 +66,53 @@ pub enum DataType {
     Undefined,
 }
 
+impl DataType {
+    /// Types whose nodes hold child nodes, either inline or through a term reference.
+    pub fn is_container(&self) -> bool {
+        matches!(self,
+            DataType::Enum | DataType::Variant | DataType::Tuple | DataType::Struct | DataType::Array |
+            DataType::Vec | DataType::Option | DataType::Result | DataType::HashSet | DataType::HashMap
+        )
+    }
+
+    pub fn is_numeric(&self) -> bool {
+        matches!(self, DataType::Int | DataType::Float)
+    }
+
+    /// Primitive values with no child nodes. `Undefined` is neither a leaf nor a container.
+    pub fn is_leaf(&self) -> bool {
+        matches!(self, DataType::Bool | DataType::Int | DataType::Float | DataType::String)
+    }
+
+    /// The `repr(u8)` discriminant, for compact binary schema encodings.
+    pub fn as_u8(&self) -> u8 {
+        self.clone() as u8
+    }
+
+    /// The inverse of `as_u8`, or `None` for a value no variant uses.
+    pub fn from_u8(value: u8) -> Option<DataType> {
+        let datatype = match value {
+            0 => DataType::Bool,
+            1 => DataType::Int,
+            2 => DataType::Float,
+            3 => DataType::String,
+            4 => DataType::Enum,
+            5 => DataType::Variant,
+            6 => DataType::Tuple,
+            7 => DataType::Struct,
+            8 => DataType::Array,
+            9 => DataType::Vec,
+            10 => DataType::Option,
+            11 => DataType::Result,
+            12 => DataType::HashSet,
+            13 => DataType::HashMap,
+            14 => DataType::Undefined,
+            _ => return None,
+        };
+        Some(datatype)
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize)]
 pub struct Type {
     pub datatype: DataType,
@@ -47,24 +136,644 @@ impl Default for Type {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize)]
+// The derives put a bound on every field type, which never resolves for the recursive `fields`
+impl BorshSerialize for Type {
+    fn serialize<W: Write>(&self, writer: &mut W) -> Result<()> {
+        self.datatype.serialize(writer)?;
+        self.name.serialize(writer)?;
+        self.term.serialize(writer)?;
+        self.signed.serialize(writer)?;
+        self.length.serialize(writer)?;
+        self.fields.serialize(writer)
+    }
+}
+
+impl BorshDeserialize for Type {
+    fn deserialize_reader<R: Read>(reader: &mut R) -> Result<Self> {
+        Ok(Type {
+            datatype: BorshDeserialize::deserialize_reader(reader)?,
+            name: BorshDeserialize::deserialize_reader(reader)?,
+            term: BorshDeserialize::deserialize_reader(reader)?,
+            signed: BorshDeserialize::deserialize_reader(reader)?,
+            length: BorshDeserialize::deserialize_reader(reader)?,
+            fields: BorshDeserialize::deserialize_reader(reader)?,
+        })
+    }
+}
+
+impl Type {
+    /// Immediate child nodes, resolving struct and enum references through the schema terms.
+    pub fn children<'a>(&'a self, schema: &'a TypeSchema) -> impl Iterator<Item = &'a Type> {
+        let fields = match (&self.fields, &self.term) {
+            (Some(fields), _) => Some(fields),
+            (None, Some(term)) => schema.terms.get(term).and_then(|t| t.fields.as_ref()),
+            (None, None) => None,
+        };
+        fields.into_iter().flatten()
+    }
+
+    /// Whether this is a `Vec<u8>`, which exporters write as a byte string rather than a list.
+    pub fn is_byte_vec(&self) -> bool {
+        self.datatype == DataType::Vec && self.fields.as_deref().and_then(<[Type]>::first)
+            .is_some_and(|e| e.datatype == DataType::Int && e.length == Some(1) && e.signed == Some(false))
+    }
+
+    /// Key and value nodes of a map. Its single child is the `(key, value)` tuple.
+    pub fn map_entry<'a>(&'a self, schema: &'a TypeSchema) -> Option<(&'a Type, &'a Type)> {
+        if self.datatype != DataType::HashMap {
+            return None;
+        }
+        let mut kv = self.children(schema).next()?.children(schema);
+        Some((kv.next()?, kv.next()?))
+    }
+
+    /// Resolves a dotted field path such as `zoom.label`, returning its elements if every one exists.
+    /// `Option` is looked through, `*`, `[]` or an index selects the element of a sequence or the
+    /// `(key, value)` entry of a map, and tuple elements are selected by index.
+    pub fn path_to(&self, schema: &TypeSchema, path: &str) -> Option<Vec<String>> {
+        let mut node = self;
+        let mut elements = Vec::new();
+        for segment in path.split('.') {
+            node = node.child_at(schema, segment)?;
+            elements.push(segment.to_owned());
+        }
+        Some(elements)
+    }
+
+    /// The child selected by one `path_to` segment.
+    fn child_at<'a>(&'a self, schema: &'a TypeSchema, segment: &str) -> Option<&'a Type> {
+        let mut node = self;
+        while node.datatype == DataType::Option {
+            node = node.children(schema).next()?;
+        }
+        match (&node.datatype, segment) {
+            (DataType::Vec | DataType::Array | DataType::HashSet | DataType::HashMap, "*" | "[]") => node.children(schema).next(),
+            (DataType::Vec | DataType::Array | DataType::HashSet | DataType::HashMap, _) => {
+                segment.parse::<usize>().ok()?;
+                node.children(schema).next()
+            },
+            (DataType::Tuple | DataType::Variant, _) => node.children(schema).nth(segment.parse().ok()?),
+            _ => node.children(schema).find(|c| c.name.as_deref() == Some(segment)),
+        }
+    }
+
+    /// Compares two nodes by datatype, width, sign and the shape of their children, ignoring field
+    /// and term names. References are resolved through each node's own schema.
+    pub fn structurally_eq(&self, other: &Type, s_self: &TypeSchema, s_other: &TypeSchema) -> bool {
+        self.structurally_eq_inner(other, s_self, s_other, &mut Vec::new())
+    }
+
+    fn structurally_eq_inner<'a>(&'a self, other: &'a Type, s_self: &'a TypeSchema, s_other: &'a TypeSchema, seen: &mut Vec<(&'a str, &'a str)>) -> bool {
+        if self.datatype != other.datatype || self.signed != other.signed || self.length != other.length {
+            return false;
+        }
+        // A pair of references already being compared is assumed equal, so recursive types terminate
+        if let (None, None, Some(a), Some(b)) = (&self.fields, &other.fields, &self.term, &other.term) {
+            if seen.contains(&(a.as_str(), b.as_str())) {
+                return true;
+            }
+            seen.push((a.as_str(), b.as_str()));
+        }
+        let children: Vec<&Type> = self.children(s_self).collect();
+        let other_children: Vec<&Type> = other.children(s_other).collect();
+        children.len() == other_children.len()
+            && children.iter().zip(other_children.iter()).all(|(a, b)| a.structurally_eq_inner(b, s_self, s_other, seen))
+    }
+
+    /// The narrowest Borsh integer holding every value in `minimum..=maximum`, the inverse of
+    /// `int_range`. Non-negative ranges get an unsigned type. Returns `None` when the range is
+    /// empty or no 128-bit type fits it.
+    pub fn int_for_range(minimum: i128, maximum: u128) -> Option<Type> {
+        if minimum >= 0 && minimum as u128 > maximum {
+            return None;
+        }
+        for length in [1u32, 2, 4, 8, 16] {
+            let shift = 128 - length * 8;
+            let (signed, fits) = if minimum >= 0 {
+                (false, maximum <= u128::MAX >> shift)
+            } else {
+                (true, minimum >= i128::MIN >> shift && maximum <= (i128::MAX >> shift) as u128)
+            };
+            if fits {
+                return Some(Type { datatype: DataType::Int, signed: Some(signed), length: Some(length), ..Type::default() });
+            }
+        }
+        None
+    }
+
+    /// Borsh-encoded size in bytes when it is the same for every value, or `None` when a string,
+    /// collection, `Option`, `Result` or recursion makes it vary. An enum is fixed only when all
+    /// of its variants encode to the same size.
+    pub fn size_bytes(&self, schema: &TypeSchema) -> Option<usize> {
+        self.size_bytes_inner(schema, &mut Vec::new())
+    }
+
+    fn size_bytes_inner<'a>(&'a self, schema: &'a TypeSchema, seen: &mut Vec<&'a str>) -> Option<usize> {
+        if let (None, Some(term)) = (&self.fields, &self.term) {
+            if seen.contains(&term.as_str()) {
+                return None;
+            }
+            seen.push(term);
+        }
+        let size = match self.datatype {
+            DataType::Bool => Some(1),
+            DataType::Int | DataType::Float => self.length.map(|l| l as usize),
+            DataType::Struct | DataType::Tuple | DataType::Variant => {
+                self.children(schema).map(|c| c.size_bytes_inner(schema, seen)).sum()
+            },
+            DataType::Array => {
+                let element = self.children(schema).next()?.size_bytes_inner(schema, seen)?;
+                Some(element * self.length? as usize)
+            },
+            DataType::Enum => {
+                let sizes: Option<Vec<usize>> = self.children(schema).map(|c| c.size_bytes_inner(schema, seen)).collect();
+                match sizes.as_deref() {
+                    Some([first, rest @ ..]) if rest.iter().all(|s| s == first) => Some(1 + first),
+                    _ => None,
+                }
+            },
+            DataType::Undefined if self.term.as_deref() == Some("nil") => Some(0),
+            _ => None,
+        };
+        if let (None, Some(_)) = (&self.fields, &self.term) {
+            seen.pop();
+        }
+        size
+    }
+
+    /// Inclusive `(minimum, maximum)` of an integer node, derived from its width and sign. `None`
+    /// for widths Borsh can't produce, which imported schemas may still carry.
+    pub fn int_range(&self) -> Option<(i128, u128)> {
+        if self.datatype != DataType::Int {
+            return None;
+        }
+        let shift = match self.length? {
+            length @ (1 | 2 | 4 | 8 | 16) => 128 - length * 8,
+            _ => return None,
+        };
+        match self.signed? {
+            true => Some((i128::MIN >> shift, (i128::MAX >> shift) as u128)),
+            false => Some((0, u128::MAX >> shift)),
+        }
+    }
+
+    /// Rust type syntax for this node, e.g. `Vec<String>` or `HashMap<String, u32>`. Structs and
+    /// enums are written as their declaration.
+    pub fn to_rust_type_string(&self, schema: &TypeSchema) -> String {
+        let children: Vec<&Type> = self.children(schema).collect();
+        let child = |index: usize| match children.get(index) {
+            Some(c) => c.to_rust_type_string(schema),
+            None => "()".to_owned(),
+        };
+        let bits = self.length.unwrap_or_default() * 8;
+        match self.datatype {
+            DataType::Bool => "bool".to_owned(),
+            DataType::Int => match self.signed {
+                Some(true) => format!("i{}", bits),
+                _ => format!("u{}", bits),
+            },
+            DataType::Float => format!("f{}", bits),
+            DataType::String => "String".to_owned(),
+            DataType::Struct | DataType::Enum => self.term.clone().unwrap_or_default(),
+            DataType::Variant | DataType::Tuple => {
+                let elements: Vec<String> = children.iter().map(|c| c.to_rust_type_string(schema)).collect();
+                match elements.len() {
+                    1 => format!("({},)", elements[0]),
+                    _ => format!("({})", elements.join(", ")),
+                }
+            },
+            DataType::Array => format!("[{}; {}]", child(0), self.length.unwrap_or_default()),
+            DataType::Vec => format!("Vec<{}>", child(0)),
+            DataType::HashSet => format!("HashSet<{}>", child(0)),
+            DataType::HashMap => match self.map_entry(schema) {
+                Some((key, value)) => format!("HashMap<{}, {}>", key.to_rust_type_string(schema), value.to_rust_type_string(schema)),
+                None => "HashMap<(), ()>".to_owned(),
+            },
+            DataType::Option => format!("Option<{}>", child(0)),
+            DataType::Result => format!("Result<{}, {}>", child(0), child(1)),
+            DataType::Undefined => match self.term.as_deref() {
+                None | Some("nil") => "()".to_owned(),
+                Some(term) => term.to_owned(),
+            },
+        }
+    }
+
+    /// Variant names of an enum whose variants carry no data, or `None` for anything else.
+    pub fn unit_variant_names<'a>(&'a self, schema: &'a TypeSchema) -> Option<Vec<&'a str>> {
+        if self.datatype != DataType::Enum {
+            return None;
+        }
+        let mut names = Vec::new();
+        for variant in self.children(schema) {
+            let unit = variant.datatype == DataType::Variant && variant.fields.as_ref().is_none_or(|f| f.is_empty());
+            if !unit {
+                return None;
+            }
+            names.push(variant.name.as_deref().unwrap_or_default());
+        }
+        Some(names)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SchemaError {
+    UnknownDeclaration(String),
+    Parse(String),
+    MissingTerm(String),
+    InvalidWidth(DataType, u32),
+    EmptyEnum(String),
+    Conflict { term: String },
+    InvalidDefinition(String),
+    DuplicateField { term: String, name: String },
+    Io(ErrorKind, String),
+}
+
+impl core::fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            SchemaError::UnknownDeclaration(declaration) => write!(f, "Unknown declaration: {}", declaration),
+            SchemaError::Parse(message) => write!(f, "Could not parse schema source: {}", message),
+            SchemaError::MissingTerm(term) => write!(f, "Reference to undefined term: {}", term),
+            SchemaError::InvalidWidth(datatype, length) => write!(f, "Invalid width for {:?}: {} bytes", datatype, length),
+            SchemaError::EmptyEnum(term) => write!(f, "Enum has no variants: {}", term),
+            SchemaError::Conflict { term } => write!(f, "Conflicting definitions for term: {}", term),
+            SchemaError::InvalidDefinition(declaration) => write!(f, "Definition does not match its declaration: {}", declaration),
+            SchemaError::DuplicateField { term, name } => write!(f, "Duplicate field name in {}: {}", term, name),
+            SchemaError::Io(kind, message) => write!(f, "I/O error ({:?}): {}", kind, message),
+        }
+    }
+}
+
+impl std::error::Error for SchemaError {}
+
+impl From<Error> for SchemaError {
+    fn from(error: Error) -> Self {
+        SchemaError::Io(error.kind(), error.to_string())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, BorshSerialize, BorshDeserialize)]
 pub struct TypeSchema {
-    pub schema: Type,
-    pub terms: HashMap<String, Type>,
+    schema: Type,
+    terms: BTreeMap<String, Type>,
+}
+
+impl TypeSchema {
+    /// Builds a schema from a container that was not necessarily produced by a Rust type, e.g. one
+    /// deserialized from storage. Every declaration it refers to must be defined or primitive, and
+    /// every definition must be of the kind its declaration implies.
+    pub fn from_container(container: &BorshSchemaContainer) -> core::result::Result<TypeSchema, SchemaError> {
+        check_declaration(container, &container.declaration)?;
+        for (declaration, definition) in container.definitions.iter() {
+            check_definition(declaration, definition)?;
+            match definition {
+                Definition::Array { elements: e, .. } => check_declaration(container, e)?,
+                Definition::Sequence { elements: e } => check_declaration(container, e)?,
+                Definition::Tuple { elements: ve } => {
+                    for e in ve {
+                        check_declaration(container, e)?;
+                    }
+                },
+                Definition::Enum { variants: v } => {
+                    for ev in v {
+                        check_declaration(container, &ev.1)?;
+                    }
+                },
+                Definition::Struct { fields: Fields::NamedFields(v) } => {
+                    for field in v {
+                        check_declaration(container, &field.1)?;
+                    }
+                },
+                Definition::Struct { fields: Fields::UnnamedFields(v) } => {
+                    for field in v {
+                        check_declaration(container, field)?;
+                    }
+                },
+                Definition::Struct { fields: Fields::Empty } => {},
+            }
+        }
+        let mut tsch = TypeSchema { schema: Type::default(), terms: BTreeMap::new() };
+        tsch.schema = get_type(container, Some(container.declaration.as_str()), &container.declaration, &mut tsch, true);
+        Ok(tsch)
+    }
+
+    /// Assembles a schema from a root and terms already in `get_type`'s shape, for importers.
+    #[cfg(any(test, feature = "graphql"))]
+    pub(crate) fn from_parts(schema: Type, terms: BTreeMap<String, Type>) -> TypeSchema {
+        TypeSchema { schema, terms }
+    }
+
+    /// The root type the schema was built from.
+    pub fn root(&self) -> &Type {
+        &self.schema
+    }
+
+    /// Named struct and enum definitions referenced from the root, keyed by declaration.
+    pub fn terms(&self) -> &BTreeMap<String, Type> {
+        &self.terms
+    }
+
+    /// Every struct and enum definition, including a struct root, sorted by term name. A recursive
+    /// root is also in `terms` and is only listed once.
+    pub fn class_terms(&self) -> Vec<(&str, &Type)> {
+        let mut classes: Vec<(&str, &Type)> = self.terms.iter()
+            .filter(|(_, t)| t.datatype == DataType::Struct || t.datatype == DataType::Enum)
+            .map(|(k, t)| (k.as_str(), t))
+            .collect();
+        if self.schema.datatype == DataType::Struct && self.schema.fields.is_some() {
+            if let Some(term) = self.schema.term.as_ref().filter(|term| !self.terms.contains_key(*term)) {
+                classes.push((term.as_str(), &self.schema));
+            }
+        }
+        classes.sort_by(|a, b| a.0.cmp(b.0));
+        classes
+    }
+
+    /// The type at a dotted path from the root, such as `thing.0.label`, following the same
+    /// segments as `Type::path_to`. A struct or enum is returned as the reference found at that
+    /// position, so use `children` to see its fields.
+    pub fn field_type(&self, dotted_path: &str) -> Option<&Type> {
+        dotted_path.split('.').try_fold(&self.schema, |node, segment| node.child_at(self, segment))
+    }
+
+    /// Compact JSON, for machines consuming the schema.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+
+    /// Indented JSON, for people inspecting the schema.
+    pub fn to_json_pretty(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    /// Compact Borsh bytes, for storing many schemas. Terms are a `BTreeMap`, so equal schemas
+    /// always encode to the same bytes.
+    pub fn to_borsh(&self) -> Vec<u8> {
+        self.try_to_vec().unwrap()
+    }
+
+    /// Reads a schema written by `to_borsh`.
+    pub fn from_borsh(bytes: &[u8]) -> Result<TypeSchema> {
+        TypeSchema::try_from_slice(bytes)
+    }
+
+    /// Calls `f` on every node, the root tree first and then each term, parents before their
+    /// children. Children are visited as they are after `f` has run on their parent.
+    pub fn walk_mut<F: FnMut(&mut Type)>(&mut self, mut f: F) {
+        walk_node_mut(&mut self.schema, &mut f);
+        for t in self.terms.values_mut() {
+            walk_node_mut(t, &mut f);
+        }
+    }
+
+    /// Canonical form for fingerprinting and comparison. Terms are already kept sorted by name, so
+    /// insertion order never matters. With `sort_fields`, struct fields and enum variants are also
+    /// sorted by name. Their order is part of the Borsh encoding, so that form only serves to
+    /// compare schemas and must not be used to encode or decode data.
+    pub fn normalized(&self, sort_fields: bool) -> TypeSchema {
+        let mut normalized = self.clone();
+        if sort_fields {
+            normalized.walk_mut(|node| {
+                if let (DataType::Struct | DataType::Enum, Some(fields)) = (&node.datatype, node.fields.as_mut()) {
+                    fields.sort_by(|a, b| a.name.cmp(&b.name));
+                }
+            });
+        }
+        normalized
+    }
+
+    /// Checks that every reference resolves to a term, no unknown declarations remain, numeric
+    /// widths are ones Borsh can produce, enums have variants and no struct or enum repeats a field
+    /// name. All problems are collected.
+    pub fn validate(&self) -> core::result::Result<(), Vec<SchemaError>> {
+        let mut errors = Vec::new();
+        self.validate_node(&self.schema, &mut errors);
+        for t in self.terms.values() {
+            self.validate_node(t, &mut errors);
+        }
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+
+    fn validate_node(&self, node: &Type, errors: &mut Vec<SchemaError>) {
+        match (&node.datatype, node.length) {
+            (DataType::Int, Some(1 | 2 | 4 | 8 | 16)) | (DataType::Float, Some(4 | 8)) => {},
+            (DataType::Int | DataType::Float, length) => errors.push(SchemaError::InvalidWidth(node.datatype.clone(), length.unwrap_or_default())),
+            // `()` is declared as `nil` and is the only expected undefined node
+            (DataType::Undefined, _) if node.term.as_deref() != Some("nil") => {
+                errors.push(SchemaError::UnknownDeclaration(node.term.clone().unwrap_or_default()));
+            },
+            (DataType::Struct | DataType::Enum, _) if is_reference(node) => {
+                let term = node.term.clone().unwrap();
+                if !self.terms.contains_key(&term) {
+                    errors.push(SchemaError::MissingTerm(term));
+                }
+            },
+            (DataType::Enum, _) if node.fields.as_ref().is_some_and(|f| f.is_empty()) => {
+                errors.push(SchemaError::EmptyEnum(node.term.clone().unwrap_or_default()));
+            },
+            _ => {},
+        }
+        if matches!(node.datatype, DataType::Struct | DataType::Enum) {
+            let mut names: Vec<&str> = Vec::new();
+            for name in node.fields.iter().flatten().filter_map(|f| f.name.as_deref()) {
+                if names.contains(&name) {
+                    errors.push(SchemaError::DuplicateField { term: node.term.clone().unwrap_or_default(), name: name.to_owned() });
+                } else {
+                    names.push(name);
+                }
+            }
+        }
+        for child in node.fields.iter().flatten() {
+            self.validate_node(child, errors);
+        }
+    }
+
+    /// Inlines every term referenced exactly once at its use site and drops it from `terms`,
+    /// returning how many were inlined. Recursive terms are always kept.
+    pub fn inline_single_use_terms(&mut self) -> usize {
+        let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+        count_references(&self.schema, &mut counts);
+        for t in self.terms.values() {
+            count_references(t, &mut counts);
+        }
+        let single: Vec<String> = counts.into_iter()
+            .filter(|(term, count)| *count == 1 && !self.is_recursive(term))
+            .map(|(term, _)| term)
+            .collect();
+        let inlined: BTreeMap<String, Type> = single.iter()
+            .filter_map(|term| self.terms.remove(term).map(|t| (term.clone(), t)))
+            .collect();
+        self.walk_mut(|node| {
+            if is_reference(node) {
+                if let Some(t) = inlined.get(node.term.as_ref().unwrap()) {
+                    *node = Type { name: node.name.clone(), ..t.clone() };
+                }
+            }
+        });
+        inlined.len()
+    }
+
+    /// Whether a term can reach itself through its fields.
+    fn is_recursive(&self, term: &str) -> bool {
+        let mut pending: Vec<&Type> = self.terms.get(term).into_iter().collect();
+        let mut visited: Vec<&str> = Vec::new();
+        while let Some(node) = pending.pop() {
+            for child in node.fields.iter().flatten() {
+                match (&child.fields, &child.term) {
+                    (None, Some(t)) if t == term => return true,
+                    (None, Some(t)) if !visited.contains(&t.as_str()) => {
+                        visited.push(t);
+                        pending.extend(self.terms.get(t));
+                    },
+                    _ => pending.push(child),
+                }
+            }
+        }
+        false
+    }
+
+    /// Depth-first walk like `TypeIterator` that doesn't descend into nodes for which `prune`
+    /// returns true. The pruned nodes themselves are still yielded and the root is always expanded.
+    pub fn iter_pruned<F: Fn(&Type) -> bool>(&self, prune: F) -> PrunedTypeIterator<'_, F> {
+        let root = PendingNode { parent: None, node: &self.schema, path: String::new(), open: Vec::new() };
+        PrunedTypeIterator { schema: self, stack: vec![root], prune }
+    }
+
+    /// Folds the terms of another schema into this one, keeping this schema's root. A struct root of
+    /// the other schema is registered as a term so it can be referenced like any nested struct.
+    pub fn merge(&mut self, other: &TypeSchema) -> core::result::Result<(), SchemaError> {
+        let mut incoming: Vec<(&String, Type)> = other.terms.iter().map(|(k, v)| (k, v.clone())).collect();
+        if other.schema.datatype == DataType::Struct && other.schema.fields.is_some() {
+            if let Some(term) = &other.schema.term {
+                incoming.push((term, Type { name: None, ..other.schema.clone() }));
+            }
+        }
+        for (term, t) in incoming.iter() {
+            match self.terms.get(*term) {
+                Some(existing) if existing != t => return Err(SchemaError::Conflict { term: term.to_string() }),
+                _ => {},
+            }
+        }
+        for (term, t) in incoming {
+            self.terms.insert(term.clone(), t);
+        }
+        Ok(())
+    }
+}
+
+impl TryFrom<&BorshSchemaContainer> for TypeSchema {
+    type Error = SchemaError;
+
+    fn try_from(container: &BorshSchemaContainer) -> core::result::Result<Self, Self::Error> {
+        TypeSchema::from_container(container)
+    }
+}
+
+/// `name` with every character outside `[A-Za-z0-9_]` replaced by `_`, so a generic declaration
+/// like `Wrapper<string>` can be used where only identifiers are allowed, e.g. in Avro or Python.
+pub fn identifier(name: &str) -> String {
+    name.chars().map(|c| if c.is_ascii_alphanumeric() || c == '_' { c } else { '_' }).collect()
 }
 
 pub fn get_schema<T: BorshSchemaTrait>() -> TypeSchema {
     let ctr = T::schema_container();
-    let mut tsch = TypeSchema { schema: Type::default(), terms: HashMap::new() };
-    tsch.schema = get_type(&ctr, Some(&ctr.declaration), &ctr.declaration, &mut tsch, true);
+    #[cfg(feature = "tracing")]
+    let _span = tracing::debug_span!("get_schema", declaration = %ctr.declaration).entered();
+    let mut tsch = TypeSchema { schema: Type::default(), terms: BTreeMap::new() };
+    tsch.schema = get_type(&ctr, Some(ctr.declaration.as_str()), &ctr.declaration, &mut tsch, true);
     tsch
 }
 
-pub fn get_type(container: &BorshSchemaContainer, field_name: Option<&String>, declaration: &String, result: &mut TypeSchema, root: bool) -> Type {
-    let name = match field_name {
-        Some(str_ref) => { Some(str_ref.to_owned()) },
-        None => None,
+/// Like `get_schema`, but fails with `UnknownDeclaration` on the first declaration `get_type`
+/// could not interpret instead of embedding an `Undefined` node. `()` is still accepted.
+pub fn get_schema_strict<T: BorshSchemaTrait>() -> core::result::Result<TypeSchema, SchemaError> {
+    fn find_undefined(node: &Type) -> Option<&Type> {
+        if node.datatype == DataType::Undefined && node.term.as_deref() != Some("nil") {
+            return Some(node);
+        }
+        node.fields.iter().flatten().find_map(find_undefined)
+    }
+    let tsch = get_schema::<T>();
+    match find_undefined(&tsch.schema).or_else(|| tsch.terms.values().find_map(find_undefined)) {
+        Some(node) => Err(SchemaError::UnknownDeclaration(node.term.clone().unwrap_or_default())),
+        None => Ok(tsch),
+    }
+}
+
+/// The raw Borsh container for `T` as JSON, showing what Borsh reported before `get_type`
+/// interpreted it. Useful when a field unexpectedly comes out `Undefined`.
+#[cfg(feature = "debug-schema")]
+pub fn borsh_container_json<T: BorshSchemaTrait>() -> String {
+    use serde_json::{json, Map, Value};
+    let ctr = T::schema_container();
+    let mut definitions = Map::new();
+    for (declaration, definition) in ctr.definitions.iter() {
+        let value = match definition {
+            Definition::Array { length, elements } => json!({ "Array": { "length": length, "elements": elements } }),
+            Definition::Sequence { elements } => json!({ "Sequence": { "elements": elements } }),
+            Definition::Tuple { elements } => json!({ "Tuple": { "elements": elements } }),
+            Definition::Enum { variants } => json!({ "Enum": { "variants": variants } }),
+            Definition::Struct { fields: Fields::NamedFields(v) } => json!({ "Struct": { "fields": v } }),
+            Definition::Struct { fields: Fields::UnnamedFields(v) } => json!({ "Struct": { "fields": v } }),
+            Definition::Struct { fields: Fields::Empty } => json!({ "Struct": { "fields": Value::Null } }),
+        };
+        definitions.insert(declaration.clone(), value);
+    }
+    json!({ "declaration": ctr.declaration, "definitions": definitions }).to_string()
+}
+
+fn is_reference(node: &Type) -> bool {
+    matches!(node.datatype, DataType::Struct | DataType::Enum) && node.fields.is_none() && node.term.is_some()
+}
+
+fn count_references(node: &Type, counts: &mut BTreeMap<String, usize>) {
+    if is_reference(node) {
+        *counts.entry(node.term.clone().unwrap()).or_default() += 1;
+    }
+    for child in node.fields.iter().flatten() {
+        count_references(child, counts);
+    }
+}
+
+fn walk_node_mut<F: FnMut(&mut Type)>(node: &mut Type, f: &mut F) {
+    f(node);
+    for child in node.fields.iter_mut().flatten() {
+        walk_node_mut(child, f);
+    }
+}
+
+/// Whether a definition has the kind `get_type` expects for its declaration, so that it doesn't
+/// come out as `Undefined` or index past the variants of an `Option` or `Result`.
+fn check_definition(declaration: &str, definition: &Definition) -> core::result::Result<(), SchemaError> {
+    let prefixed = |prefixes: &[&str]| prefixes.iter().any(|p| declaration.starts_with(p));
+    let valid = if prefixed(&["Option<", "Result<"]) {
+        matches!(definition, Definition::Enum { variants } if variants.len() == 2)
+    } else if prefixed(&["Vec<", "HashSet<", "HashMap<"]) {
+        matches!(definition, Definition::Sequence { .. })
+    } else if prefixed(&["Tuple<"]) {
+        matches!(definition, Definition::Tuple { .. })
+    } else if prefixed(&["Array<"]) {
+        matches!(definition, Definition::Array { .. })
+    } else {
+        !matches!(definition, Definition::Tuple { .. })
     };
+    if valid { Ok(()) } else { Err(SchemaError::InvalidDefinition(declaration.to_owned())) }
+}
+
+fn check_declaration(container: &BorshSchemaContainer, declaration: &str) -> core::result::Result<(), SchemaError> {
+    if container.definitions.contains_key(declaration) {
+        return Ok(());
+    }
+    let re_primitive = regex!(r"^(bool|string|String|nil|[ui](8|16|32|64|128)|f(32|64))$");
+    if re_primitive.is_match(declaration) {
+        Ok(())
+    } else {
+        Err(SchemaError::UnknownDeclaration(declaration.to_owned()))
+    }
+}
+
+pub fn get_type(container: &BorshSchemaContainer, field_name: Option<&str>, declaration: &str, result: &mut TypeSchema, root: bool) -> Type {
+    let name = field_name.map(str::to_owned);
     if !(
         declaration.starts_with("HashSet<") || declaration.starts_with("HashMap<") ||
         declaration.starts_with("Option<") || declaration.starts_with("Result<")
@@ -74,93 +783,108 @@ pub fn get_type(container: &BorshSchemaContainer, field_name: Option<&String>, d
             match definition.unwrap() {
                 Definition::Struct {fields: f} => {
                     match f {
-                        Fields::NamedFields(v) => {
+                        // A unit struct is still a class, so it is kept as a struct with no fields
+                        Fields::NamedFields(_) | Fields::Empty => {
+                            let v: &[_] = match f {
+                                Fields::NamedFields(v) => v,
+                                _ => &[],
+                            };
                             if root {
                                 let mut fs = Vec::new();
                                 for field in v {
-                                    fs.push(get_type(container, Some(&field.0), &field.1, result, false));
+                                    fs.push(get_type(container, Some(field.0.as_str()), &field.1, result, false));
                                 }
-                                return Type { datatype: DataType::Struct, name: name.clone(), length: None, signed: None, fields: Some(fs), term: Some(declaration.clone()) };
+                                return Type { datatype: DataType::Struct, name, length: None, signed: None, fields: Some(fs), term: Some(declaration.to_owned()) };
                             } else {
-                                let found_struct = result.terms.get(&declaration.clone());
+                                let found_struct = result.terms.get(declaration);
                                 if found_struct == None {
+                                    // Register the term before its fields so recursive references resolve to it
+                                    result.terms.insert(declaration.to_owned(), Type { datatype: DataType::Struct, term: Some(declaration.to_owned()), ..Type::default() });
                                     let mut fs = Vec::new();
                                     for field in v {
-                                        fs.push(get_type(container, Some(&field.0), &field.1, result, false));
+                                        fs.push(get_type(container, Some(field.0.as_str()), &field.1, result, false));
                                     }
-                                    let ts = Type { datatype: DataType::Struct, name: None, length: None, signed: None, fields: Some(fs), term: Some(declaration.clone()) };
-                                    result.terms.insert(declaration.clone(), ts.clone());
+                                    let ts = Type { datatype: DataType::Struct, name: None, length: None, signed: None, fields: Some(fs), term: Some(declaration.to_owned()) };
+                                    result.terms.insert(declaration.to_owned(), ts);
                                 }
-                                return Type { datatype: DataType::Struct, name: name.clone(), length: None, signed: None, fields: None, term: Some(declaration.clone()) };
+                                return Type { datatype: DataType::Struct, name, length: None, signed: None, fields: None, term: Some(declaration.to_owned()) };
                             }
                         },
                         Fields::UnnamedFields(v) => {
                             let mut fields = Vec::new();
                             for field in v {
-                                fields.push(get_type(container, None, &field, result, false));
+                                fields.push(get_type(container, None, field, result, false));
                             }
-                            return Type {datatype: DataType::Variant, name: name.clone(), length: Some(v.len() as u32), signed: None, fields: Some(fields), term: None };
+                            return Type {datatype: DataType::Variant, name, length: Some(v.len() as u32), signed: None, fields: Some(fields), term: None };
                         },
-                        Fields::Empty => return Type { datatype: DataType::Variant, name: name.clone(), length: None, signed: None, fields: None, term: None },
                     }
                 },
                 Definition::Array { elements: e, length: l } => {
-                    let fields = vec![get_type(container, None, &e, result, false)];
-                    return Type {datatype: DataType::Array, name: name.clone(), length: Some(l.to_owned()), signed: None, fields: Some(fields), term: None }
+                    let fields = vec![get_type(container, None, e, result, false)];
+                    return Type {datatype: DataType::Array, name, length: Some(l.to_owned()), signed: None, fields: Some(fields), term: None }
                 },
                 Definition::Sequence { elements: e } => {
-                    let fields = vec![get_type(container, None, &e, result, false)];
-                    return Type {datatype: DataType::Vec, name: name.clone(), length: None, signed: None, fields: Some(fields), term: None }
+                    let fields = vec![get_type(container, None, e, result, false)];
+                    return Type {datatype: DataType::Vec, name, length: None, signed: None, fields: Some(fields), term: None }
                 },
                 Definition::Enum {variants: v} => {
-                    let found_enum = result.terms.get(&declaration.clone());
+                    let found_enum = result.terms.get(declaration);
                     if found_enum == None {
+                        result.terms.insert(declaration.to_owned(), Type { datatype: DataType::Enum, term: Some(declaration.to_owned()), ..Type::default() });
                         let mut enums = Vec::new();
                         for ev in v {
-                            enums.push(get_type(container, Some(&ev.0), &ev.1, result, false));
+                            // Borsh reports unit variants as empty structs too; only here are they known to be variants
+                            let variant = match container.definitions.get(&ev.1) {
+                                Some(Definition::Struct { fields: Fields::Empty }) => {
+                                    Type { datatype: DataType::Variant, name: Some(ev.0.clone()), ..Type::default() }
+                                },
+                                _ => get_type(container, Some(ev.0.as_str()), &ev.1, result, false),
+                            };
+                            enums.push(variant);
                         }
-                        let ts = Type {datatype: DataType::Enum, name: None, length: Some(v.len() as u32), signed: None, fields: Some(enums), term: Some(declaration.clone()) };
-                        result.terms.insert(declaration.clone(), ts.clone());
+                        let ts = Type {datatype: DataType::Enum, name: None, length: Some(v.len() as u32), signed: None, fields: Some(enums), term: Some(declaration.to_owned()) };
+                        result.terms.insert(declaration.to_owned(), ts);
                     }
-                    return Type {datatype: DataType::Enum, name: name.clone(), length: None, signed: None, fields: None, term: Some(declaration.clone()) }
+                    return Type {datatype: DataType::Enum, name, length: None, signed: None, fields: None, term: Some(declaration.to_owned()) }
                 },
                 _ => {},
             }
         }
     }
-    match declaration.as_str() {
-        "bool" => return Type { datatype: DataType::Bool, name: name.clone(), signed: None, length: None, fields: None, term: None},
-        "string" => return Type { datatype: DataType::String, name: name.clone(), signed: None, length: None, fields: None, term: None},
+    match declaration {
+        "bool" => return Type { datatype: DataType::Bool, name, signed: None, length: None, fields: None, term: None},
+        // Borsh 0.10 declares `String` as `string`; later versions spell it `String`
+        "string" | "String" => return Type { datatype: DataType::String, name, signed: None, length: None, fields: None, term: None},
         _ => {},
     };
-    let re_unsigned_int = Regex::new(r"^u(\d+)$").unwrap();
+    let re_unsigned_int = regex!(r"^u(\d+)$");
     match re_unsigned_int.captures(declaration) {
         Some(bits_info) => {
             let bytes = bits_info.get(1).unwrap().as_str().parse::<u32>().unwrap().checked_div(8).unwrap();
             if !(bytes == 1 || bytes == 2 || bytes == 4 || bytes == 8 || bytes == 16) { panic!("Invalid unsigned integer bytes") }
-            return Type {datatype: DataType::Int, name: name.clone(), signed: Some(false), length: Some(bytes), fields: None, term: None}
+            return Type {datatype: DataType::Int, name, signed: Some(false), length: Some(bytes), fields: None, term: None}
         },
         None => {},
     }
-    let re_signed_int = Regex::new(r"^i(\d+)$").unwrap();
+    let re_signed_int = regex!(r"^i(\d+)$");
     match re_signed_int.captures(declaration) {
         Some(bits_info) => {
             let bytes = bits_info.get(1).unwrap().as_str().parse::<u32>().unwrap().checked_div(8).unwrap();
             if !(bytes == 1 || bytes == 2 || bytes == 4 || bytes == 8 || bytes == 16) { panic!("Invalid signed integer bytes") }
-            return Type {datatype: DataType::Int, name: name.clone(), signed: Some(true), length: Some(bytes), fields: None, term: None}
+            return Type {datatype: DataType::Int, name, signed: Some(true), length: Some(bytes), fields: None, term: None}
         },
         None => {},
     }
-    let re_float = Regex::new(r"^f(\d+)$").unwrap();
+    let re_float = regex!(r"^f(\d+)$");
     match re_float.captures(declaration) {
         Some(bits_info) => {
             let bytes = bits_info.get(1).unwrap().as_str().parse::<u32>().unwrap().checked_div(8).unwrap();
             if !(bytes == 4 || bytes == 8) { panic!("Invalid signed integer bytes") }
-            return Type {datatype: DataType::Float, name: name.clone(), length: Some(bytes), signed: None, fields: None, term: None}
+            return Type {datatype: DataType::Float, name, length: Some(bytes), signed: None, fields: None, term: None}
         },
         None => {},
     }
-    let re_tuple = Regex::new(r"^Tuple<.*>$").unwrap();
+    let re_tuple = regex!(r"^Tuple<.*>$");
     match re_tuple.captures(declaration) {
         Some(tuple_txt) => {
             let tuple_def = container.definitions.get(tuple_txt.get(0).unwrap().as_str()).unwrap();
@@ -168,58 +892,58 @@ pub fn get_type(container: &BorshSchemaContainer, field_name: Option<&String>, d
                 Definition::Tuple { elements: ve } => {
                     let mut fields = Vec::new();
                     for e in ve {
-                        fields.push(get_type(container, None, &e, result, false));
+                        fields.push(get_type(container, None, e, result, false));
                     }
-                    return Type {datatype: DataType::Tuple, name: name.clone(), length: Some(ve.len() as u32), signed: None, fields: Some(fields), term: None }
+                    return Type {datatype: DataType::Tuple, name, length: Some(ve.len() as u32), signed: None, fields: Some(fields), term: None }
                 },
                 _ => {}
             }
         },
         None => {},
     }
-    let re_array = Regex::new(r"^Array<.*>$").unwrap();
+    let re_array = regex!(r"^Array<.*>$");
     match re_array.captures(declaration) {
         Some(array_txt) => {
             let array_def = container.definitions.get(array_txt.get(0).unwrap().as_str()).unwrap();
             match array_def {
                 Definition::Array { elements: e, length: l } => {
-                    let fields = vec![get_type(container, None, &e, result, false)];
-                    return Type {datatype: DataType::Array, name: name.clone(), length: Some(l.to_owned()), signed: None, fields: Some(fields), term: None }
+                    let fields = vec![get_type(container, None, e, result, false)];
+                    return Type {datatype: DataType::Array, name, length: Some(l.to_owned()), signed: None, fields: Some(fields), term: None }
                 },
                 _ => {}
             }
         },
         None => {},
     }
-    let re_vec = Regex::new(r"^Vec<.*>$").unwrap();
+    let re_vec = regex!(r"^Vec<.*>$");
     match re_vec.captures(declaration) {
         Some(vec_txt) => {
             let vec_def = container.definitions.get(vec_txt.get(0).unwrap().as_str()).unwrap();
             match vec_def {
                 Definition::Sequence { elements: e } => {
-                    let fields = vec![get_type(container, None, &e, result, false)];
-                    return Type {datatype: DataType::Vec, name: name.clone(), length: None, signed: None, fields: Some(fields), term: None }
+                    let fields = vec![get_type(container, None, e, result, false)];
+                    return Type {datatype: DataType::Vec, name, length: None, signed: None, fields: Some(fields), term: None }
                 },
                 _ => {}
             }
         },
         None => {},
     }
-    let re_option = Regex::new(r"^Option<.*>$").unwrap();
+    let re_option = regex!(r"^Option<.*>$");
     match re_option.captures(declaration) {
         Some(option_txt) => {
             let option_def = container.definitions.get(option_txt.get(0).unwrap().as_str()).unwrap();
             match option_def {
                 Definition::Enum { variants: v } => {
                     let fields = vec![get_type(container, None, &v[1].1, result, false)];
-                    return Type {datatype: DataType::Option, name: name.clone(), length: None, signed: None, fields: Some(fields), term: None }
+                    return Type {datatype: DataType::Option, name, length: None, signed: None, fields: Some(fields), term: None }
                 },
                 _ => {}
             }
         },
         None => {},
     }
-    let re_result = Regex::new(r"^Result<.*>$").unwrap();
+    let re_result = regex!(r"^Result<.*>$");
     match re_result.captures(declaration) {
         Some(result_txt) => {
             let result_def = container.definitions.get(result_txt.get(0).unwrap().as_str()).unwrap();
@@ -229,90 +953,789 @@ pub fn get_type(container: &BorshSchemaContainer, field_name: Option<&String>, d
                         get_type(container, None, &v[0].1, result, false), // Ok
                         get_type(container, None, &v[1].1, result, false), // Err
                     ];
-                    return Type {datatype: DataType::Result, name: name.clone(), length: None, signed: None, fields: Some(fields), term: None }
+                    return Type {datatype: DataType::Result, name, length: None, signed: None, fields: Some(fields), term: None }
                 },
                 _ => {}
             }
         },
         None => {},
     }
-    let re_hashset = Regex::new(r"^HashSet<.*>$").unwrap();
+    let re_hashset = regex!(r"^HashSet<.*>$");
     match re_hashset.captures(declaration) {
         Some(hashset_txt) => {
             let hashset_def = container.definitions.get(hashset_txt.get(0).unwrap().as_str()).unwrap();
             match hashset_def {
                 Definition::Sequence { elements: e } => {
-                    let fields = vec![get_type(container, None, &e, result, false)];
-                    return Type {datatype: DataType::HashSet, name: name.clone(), length: None, signed: None, fields: Some(fields), term: None }
+                    let fields = vec![get_type(container, None, e, result, false)];
+                    return Type {datatype: DataType::HashSet, name, length: None, signed: None, fields: Some(fields), term: None }
                 },
                 _ => {}
             }
         },
         None => {},
     }
-    let re_hashmap = Regex::new(r"^HashMap<.*>$").unwrap();
+    let re_hashmap = regex!(r"^HashMap<.*>$");
     match re_hashmap.captures(declaration) {
         Some(hashmap_txt) => {
             let hashmap_def = container.definitions.get(hashmap_txt.get(0).unwrap().as_str()).unwrap();
             match hashmap_def {
                 Definition::Sequence { elements: e } => {
-                    let fields = vec![get_type(container, None, &e, result, false)];
-                    return Type {datatype: DataType::HashMap, name: name.clone(), length: None, signed: None, fields: Some(fields), term: None }
+                    let fields = vec![get_type(container, None, e, result, false)];
+                    return Type {datatype: DataType::HashMap, name, length: None, signed: None, fields: Some(fields), term: None }
                 },
                 _ => {}
             }
         },
         None => {},
     }
-    Type::default()
+    // Keep unclassified declarations visible rather than silently dropping them
+    #[cfg(feature = "tracing")]
+    tracing::warn!(%declaration, "unknown declaration");
+    Type { datatype: DataType::Undefined, name, signed: None, length: None, fields: None, term: Some(declaration.to_owned()) }
+}
+
+/// Dotted location of a child node, matching the paths `Type::path_to` accepts: fields by name,
+/// tuple and variant elements by index, `*` for the element of a sequence or map, and `Option`
+/// sharing its inner node's path.
+fn child_path(path: &str, node: &Type, index: usize, child: &Type) -> String {
+    let element = match node.datatype {
+        DataType::Option => return path.to_owned(),
+        DataType::Vec | DataType::Array | DataType::HashSet | DataType::HashMap => "*".to_owned(),
+        DataType::Tuple | DataType::Variant => index.to_string(),
+        _ => child.name.clone().unwrap_or_else(|| index.to_string()),
+    };
+    if path.is_empty() { element } else { format!("{}.{}", path, element) }
+}
+
+/// A node waiting on an iterator's stack, with the terms being expanded on its path from the root.
+struct PendingNode<'a> {
+    parent: Option<&'a Type>,
+    node: &'a Type,
+    path: String,
+    open: Vec<&'a str>,
+}
+
+/// Pushes the children of `pending` in reverse so they pop in declaration order. A reference to a
+/// term that is already open on the path is left as a leaf, which keeps recursive types finite.
+fn push_children<'a>(schema: &'a TypeSchema, stack: &mut Vec<PendingNode<'a>>, pending: &PendingNode<'a>) {
+    let node = pending.node;
+    if node.datatype == DataType::Undefined {
+        return;
+    }
+    let mut open = pending.open.clone();
+    let mut owner = node;
+    if let (DataType::Struct | DataType::Enum, Some(term)) = (&node.datatype, &node.term) {
+        if open.contains(&term.as_str()) {
+            return;
+        }
+        open.push(term);
+        if is_reference(node) {
+            match schema.terms.get(term) {
+                Some(t) => owner = t,
+                None => return,
+            }
+        }
+    }
+    let fields: &[Type] = owner.fields.as_deref().unwrap_or_default();
+    for (index, child) in fields.iter().enumerate().rev() {
+        stack.push(PendingNode { parent: Some(owner), node: child, path: child_path(&pending.path, owner, index, child), open: open.clone() });
+    }
 }
 
+/// Depth-first walk over the schema. Each item is `(parent, node, path)`, where `path` is the
+/// node's dotted location from the root (empty for the root itself). A recursive reference is
+/// yielded once per cycle and not expanded again.
 pub struct TypeIterator<'a, T> {
     schema: &'a TypeSchema,
-    stack: Vec<(Option<&'a Type>, &'a Type)>,
+    stack: Vec<PendingNode<'a>>,
     data: PhantomData<&'a T>,
 }
 
 impl<'a, T: BorshSchemaTrait> TypeIterator<'a, T> {
     pub fn new(schema: &'a TypeSchema) -> TypeIterator<'a, T> {
-        TypeIterator { stack: vec![(None, &schema.schema)], schema: schema, data: PhantomData {} }
+        let root = PendingNode { parent: None, node: &schema.schema, path: String::new(), open: Vec::new() };
+        TypeIterator { stack: vec![root], schema, data: PhantomData {} }
     }
+}
 
-    fn add_child_nodes(&mut self, node: &'a Type, lookup: bool, schema: &'a TypeSchema) {
-        if node.fields.is_some() {
-            let subfields: &Vec<Type> = &node.fields.as_ref().unwrap().as_ref();
-            for child in subfields.iter().rev() {
-                self.stack.push((Some(node), child));
-            }
-        } else if lookup {
-            let rnode = schema.terms.get(&node.term.clone().unwrap());
-            if rnode.is_some() {
-                self.add_nodes(&rnode.unwrap(), schema);
-            }
+impl<'a, T: BorshSchemaTrait> Iterator for TypeIterator<'a, T> {
+    type Item = (Option<&'a Type>, &'a Type, String);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let pending = self.stack.pop()?;
+        push_children(self.schema, &mut self.stack, &pending);
+        Some((pending.parent, pending.node, pending.path))
+    }
+}
+
+pub struct PrunedTypeIterator<'a, F> {
+    schema: &'a TypeSchema,
+    stack: Vec<PendingNode<'a>>,
+    prune: F,
+}
+
+impl<'a, F: Fn(&Type) -> bool> Iterator for PrunedTypeIterator<'a, F> {
+    type Item = (Option<&'a Type>, &'a Type, String);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let pending = self.stack.pop()?;
+        if pending.parent.is_none() || !(self.prune)(pending.node) {
+            push_children(self.schema, &mut self.stack, &pending);
+        }
+        Some((pending.parent, pending.node, pending.path))
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use borsh_derive::BorshSchema;
+
+    /// `struct List { value: u32, next: Option<Box<List>> }`, which the Borsh 0.10 derive can't
+    /// describe without overflowing.
+    pub(crate) fn linked_list_container() -> BorshSchemaContainer {
+        let mut definitions = borsh::maybestd::collections::HashMap::new();
+        definitions.insert("List".to_owned(), Definition::Struct {
+            fields: Fields::NamedFields(vec![("value".to_owned(), "u32".to_owned()), ("next".to_owned(), "Option<List>".to_owned())]),
+        });
+        definitions.insert("Option<List>".to_owned(), Definition::Enum {
+            variants: vec![("None".to_owned(), "nil".to_owned()), ("Some".to_owned(), "List".to_owned())],
+        });
+        BorshSchemaContainer { declaration: "List".to_owned(), definitions }
+    }
+
+    struct List {
+        value: u32,
+        next: Option<Box<List>>,
+    }
+
+    impl BorshSerialize for List {
+        fn serialize<W: Write>(&self, writer: &mut W) -> Result<()> {
+            self.value.serialize(writer)?;
+            self.next.serialize(writer)
         }
     }
 
-    fn add_nodes(&mut self, node: &'a Type, schema: &'a TypeSchema) {
+    /// Reads the integers of a Borsh-encoded value by following its schema.
+    fn read_ints(schema: &TypeSchema, node: &Type, bytes: &mut &[u8], out: &mut Vec<u64>) {
         match node.datatype {
-            DataType::Undefined => {},
-            DataType::Struct => self.add_child_nodes(node, true, schema),
-            DataType::Enum => self.add_child_nodes(node, true, schema),
-            _ => self.add_child_nodes(node, false, schema),
+            DataType::Int => {
+                let (value, rest) = bytes.split_at(node.length.unwrap() as usize);
+                out.push(value.iter().rev().fold(0, |acc, b| acc << 8 | *b as u64));
+                *bytes = rest;
+            },
+            DataType::Option => {
+                let (tag, rest) = bytes.split_first().unwrap();
+                *bytes = rest;
+                if *tag == 1 {
+                    read_ints(schema, node.children(schema).next().unwrap(), bytes, out);
+                }
+            },
+            _ => {
+                for child in node.children(schema) {
+                    read_ints(schema, child, bytes, out);
+                }
+            },
         }
     }
-}
 
-impl<'a, T: BorshSchemaTrait> Iterator for TypeIterator<'a, T> {
-    type Item = (Option<&'a Type>, &'a Type);
+    #[derive(BorshSchema)]
+    #[allow(dead_code)]
+    enum Something { A, B }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        match self.stack.pop() {
-            None => None,
-            Some((parent, node)) => {
-                self.add_nodes(&node, &self.schema);
-                Some((parent, node))
+    #[derive(BorshSchema)]
+    #[allow(dead_code)]
+    struct Other { label: String, cool: bool, some: Something }
+
+    #[derive(BorshSchema)]
+    #[allow(dead_code)]
+    struct Person { name: String, uuid: u128, thing: Other, vector: Vec<(u128, u64, String)>, others: Vec<Other> }
+
+    fn int(signed: bool, length: u32) -> Type {
+        Type { datatype: DataType::Int, signed: Some(signed), length: Some(length), ..Type::default() }
+    }
+
+    #[test]
+    fn int_range_follows_width_and_sign() {
+        assert_eq!(int(false, 1).int_range(), Some((0, 255)));
+        assert_eq!(int(true, 1).int_range(), Some((-128, 127)));
+        assert_eq!(int(true, 16).int_range(), Some((i128::MIN, i128::MAX as u128)));
+        assert_eq!(int(false, 16).int_range(), Some((0, u128::MAX)));
+    }
+
+    #[test]
+    fn int_range_rejects_invalid_widths() {
+        for length in [0, 3, 17, 32, u32::MAX] {
+            assert_eq!(int(false, length).int_range(), None);
+            assert_eq!(int(true, length).int_range(), None);
+        }
+    }
+
+    #[test]
+    fn int_for_range_picks_the_narrowest_width() {
+        assert_eq!(Type::int_for_range(0, 255), Some(int(false, 1)));
+        assert_eq!(Type::int_for_range(0, 256), Some(int(false, 2)));
+        assert_eq!(Type::int_for_range(-128, 127), Some(int(true, 1)));
+        assert_eq!(Type::int_for_range(-129, 127), Some(int(true, 2)));
+        assert_eq!(Type::int_for_range(-128, 128), Some(int(true, 2)));
+        assert_eq!(Type::int_for_range(0, u128::MAX), Some(int(false, 16)));
+        assert_eq!(Type::int_for_range(i128::MIN, i128::MAX as u128), Some(int(true, 16)));
+        assert_eq!(Type::int_for_range(5, 5), Some(int(false, 1)));
+    }
+
+    #[test]
+    fn int_for_range_rejects_empty_and_unrepresentable_ranges() {
+        assert_eq!(Type::int_for_range(5, 3), None);
+        assert_eq!(Type::int_for_range(i128::MAX, 0), None);
+        assert_eq!(Type::int_for_range(-1, u128::MAX), None);
+        assert_eq!(Type::int_for_range(i128::MIN, i128::MAX as u128 + 1), None);
+    }
+
+    #[test]
+    fn merge_shares_common_terms() {
+        #[derive(BorshSchema)]
+        #[allow(dead_code)]
+        struct Team { lead: Other, members: Vec<Other> }
+        let mut registry = get_schema::<Person>();
+        registry.merge(&get_schema::<Team>()).unwrap();
+        assert_eq!(registry.root(), get_schema::<Person>().root());
+        assert_eq!(registry.terms().keys().collect::<Vec<_>>(), vec!["Other", "Something", "Team"]);
+        assert_eq!(registry.terms()["Other"], get_schema::<Person>().terms()["Other"]);
+    }
+
+    #[test]
+    fn merge_rejects_conflicting_terms() {
+        mod other {
+            #[derive(borsh_derive::BorshSchema)]
+            #[allow(dead_code)]
+            pub struct Other { label: u32 }
+        }
+        #[derive(BorshSchema)]
+        #[allow(dead_code)]
+        struct Team { lead: other::Other }
+        let mut registry = get_schema::<Person>();
+        let before = registry.clone();
+        assert_eq!(registry.merge(&get_schema::<Team>()), Err(SchemaError::Conflict { term: "Other".to_owned() }));
+        assert_eq!(registry, before);
+    }
+
+    #[test]
+    fn validate_flags_duplicate_field_names() {
+        let field = |name: &str| Type { name: Some(name.to_owned()), ..int(false, 4) };
+        let root = Type { datatype: DataType::Struct, term: Some("Person".to_owned()), fields: Some(vec![field("name"), field("age"), field("name")]), ..Type::default() };
+        let tsch = TypeSchema::from_parts(root, BTreeMap::new());
+        assert_eq!(tsch.validate(), Err(vec![SchemaError::DuplicateField { term: "Person".to_owned(), name: "name".to_owned() }]));
+        assert_eq!(get_schema::<Person>().validate(), Ok(()));
+    }
+
+    #[test]
+    fn class_terms_lists_struct_and_enum_terms() {
+        let tsch = get_schema::<Person>();
+        let classes: Vec<&str> = tsch.class_terms().into_iter().map(|(term, _)| term).collect();
+        assert_eq!(classes, vec!["Other", "Person", "Something"]);
+    }
+
+    #[test]
+    fn class_terms_lists_a_recursive_root_once() {
+        let tsch = TypeSchema::from_container(&linked_list_container()).unwrap();
+        let classes: Vec<&str> = tsch.class_terms().into_iter().map(|(term, _)| term).collect();
+        assert_eq!(classes, vec!["List"]);
+    }
+
+    #[test]
+    fn recursive_term_is_a_single_reference() {
+        let tsch = TypeSchema::from_container(&linked_list_container()).unwrap();
+        let option = tsch.field_type("next").unwrap();
+        assert_eq!(option.datatype, DataType::Option);
+        let next = option.children(&tsch).next().unwrap();
+        assert!(is_reference(next));
+        assert_eq!(next.term.as_deref(), Some("List"));
+        assert!(tsch.terms().contains_key("List"));
+    }
+
+    #[test]
+    fn iterators_stop_at_recursive_references() {
+        let tsch = TypeSchema::from_container(&linked_list_container()).unwrap();
+        let nodes: Vec<(DataType, String)> = TypeIterator::<()>::new(&tsch).map(|(_, node, path)| (node.datatype.clone(), path)).collect();
+        assert_eq!(nodes, vec![
+            (DataType::Struct, "".to_owned()),
+            (DataType::Int, "value".to_owned()),
+            (DataType::Option, "next".to_owned()),
+            (DataType::Struct, "next".to_owned()),
+        ]);
+        assert_eq!(tsch.iter_pruned(|_| false).count(), 4);
+    }
+
+    #[test]
+    fn decodes_three_element_linked_list() {
+        let list = List { value: 1, next: Some(Box::new(List { value: 2, next: Some(Box::new(List { value: 3, next: None })) })) };
+        let bytes = list.try_to_vec().unwrap();
+        let tsch = TypeSchema::from_container(&linked_list_container()).unwrap();
+        let mut values = Vec::new();
+        let mut rest = bytes.as_slice();
+        read_ints(&tsch, tsch.root(), &mut rest, &mut values);
+        assert_eq!(values, vec![1, 2, 3]);
+        assert!(rest.is_empty());
+    }
+
+    #[test]
+    fn schema_macro_matches_get_schema() {
+        #[derive(BorshSchema)]
+        #[allow(dead_code)]
+        struct Person { name: String, age: u32, tags: Vec<String> }
+        let tsch = crate::schema! {
+            struct Person { name: String, age: u32, tags: Vec<String> }
+        };
+        assert_eq!(tsch, get_schema::<Person>());
+    }
+
+    #[test]
+    fn schema_macro_resolves_extra_structs_as_terms() {
+        #[derive(BorshSchema)]
+        #[allow(dead_code)]
+        struct Address { street: String, number: u16 }
+        #[derive(BorshSchema)]
+        #[allow(dead_code)]
+        struct Person { name: String, home: Address, previous: Option<Address> }
+        let tsch = crate::schema! {
+            struct Person { name: String, home: Address, previous: Option<Address> }
+            struct Address { street: String, number: u16 }
+        };
+        assert_eq!(tsch, get_schema::<Person>());
+        assert_eq!(tsch.terms().keys().collect::<Vec<_>>(), vec!["Address"]);
+    }
+
+    #[test]
+    fn from_container_matches_derived_schema() {
+        #[derive(BorshSchema)]
+        #[allow(dead_code)]
+        struct Point { x: u32, y: u32 }
+        let mut definitions = borsh::maybestd::collections::HashMap::new();
+        definitions.insert("Point".to_owned(), Definition::Struct {
+            fields: Fields::NamedFields(vec![("x".to_owned(), "u32".to_owned()), ("y".to_owned(), "u32".to_owned())]),
+        });
+        let container = BorshSchemaContainer { declaration: "Point".to_owned(), definitions };
+        assert_eq!(TypeSchema::from_container(&container).unwrap(), get_schema::<Point>());
+    }
+
+    #[test]
+    fn from_container_rejects_undefined_declarations() {
+        let mut definitions = borsh::maybestd::collections::HashMap::new();
+        definitions.insert("Point".to_owned(), Definition::Struct {
+            fields: Fields::NamedFields(vec![("x".to_owned(), "u32".to_owned()), ("at".to_owned(), "Location".to_owned())]),
+        });
+        let container = BorshSchemaContainer { declaration: "Point".to_owned(), definitions };
+        assert_eq!(TypeSchema::from_container(&container), Err(SchemaError::UnknownDeclaration("Location".to_owned())));
+    }
+
+    #[test]
+    fn from_container_rejects_mismatched_definitions() {
+        let container = |declaration: &str, definition: Definition| {
+            let mut definitions = borsh::maybestd::collections::HashMap::new();
+            definitions.insert("Point".to_owned(), Definition::Struct {
+                fields: Fields::NamedFields(vec![("at".to_owned(), declaration.to_owned())]),
+            });
+            definitions.insert(declaration.to_owned(), definition);
+            BorshSchemaContainer { declaration: "Point".to_owned(), definitions }
+        };
+        let single = container("Option<u8>", Definition::Enum { variants: vec![("None".to_owned(), "nil".to_owned())] });
+        assert_eq!(TypeSchema::from_container(&single), Err(SchemaError::InvalidDefinition("Option<u8>".to_owned())));
+        let tuple = container("Vec<u8>", Definition::Tuple { elements: vec!["u8".to_owned()] });
+        assert_eq!(TypeSchema::from_container(&tuple), Err(SchemaError::InvalidDefinition("Vec<u8>".to_owned())));
+        let option = container("Option<u8>", Definition::Enum {
+            variants: vec![("None".to_owned(), "nil".to_owned()), ("Some".to_owned(), "u8".to_owned())],
+        });
+        assert_eq!(TypeSchema::from_container(&option).unwrap().field_type("at").unwrap().datatype, DataType::Option);
+    }
+
+    #[test]
+    fn path_to_follows_fields_sequences_and_tuples() {
+        let tsch = get_schema::<Person>();
+        let root = tsch.root();
+        let path = |p: &str| root.path_to(&tsch, p);
+        assert_eq!(path("thing.label"), Some(vec!["thing".to_owned(), "label".to_owned()]));
+        assert_eq!(path("others.*.some"), Some(vec!["others".to_owned(), "*".to_owned(), "some".to_owned()]));
+        assert!(path("others.3.label").is_some());
+        assert!(path("vector.[].2").is_some());
+        assert_eq!(path("vector.*.3"), None);
+        assert_eq!(path("thing.missing"), None);
+        assert_eq!(path("others.first"), None);
+    }
+
+    #[test]
+    fn inline_single_use_terms_keeps_shared_terms() {
+        let mut tsch = get_schema::<Person>();
+        // `Something` is only used by `Other`, which `thing` and `others` share
+        assert_eq!(tsch.inline_single_use_terms(), 1);
+        assert_eq!(tsch.terms().keys().collect::<Vec<_>>(), vec!["Other"]);
+        let some = tsch.terms()["Other"].fields.as_ref().unwrap().iter().find(|f| f.name.as_deref() == Some("some")).unwrap();
+        assert_eq!(some.datatype, DataType::Enum);
+        assert_eq!(some.term.as_deref(), Some("Something"));
+        assert_eq!(some.fields.as_ref().map(Vec::len), Some(2));
+        assert_eq!(tsch.validate(), Ok(()));
+        assert_eq!(tsch.inline_single_use_terms(), 0);
+    }
+
+    #[test]
+    fn inline_single_use_terms_keeps_recursive_terms() {
+        let mut tsch = TypeSchema::from_container(&linked_list_container()).unwrap();
+        let before = tsch.clone();
+        assert_eq!(tsch.inline_single_use_terms(), 0);
+        assert_eq!(tsch, before);
+    }
+
+    #[test]
+    fn validate_collects_every_problem() {
+        assert_eq!(get_schema::<Person>().validate(), Ok(()));
+        assert_eq!(TypeSchema::from_container(&linked_list_container()).unwrap().validate(), Ok(()));
+        let ghost = Type { datatype: DataType::Struct, name: Some("ghost".to_owned()), term: Some("Ghost".to_owned()), ..Type::default() };
+        let empty = Type { datatype: DataType::Enum, name: Some("empty".to_owned()), term: Some("Empty".to_owned()), fields: Some(Vec::new()), ..Type::default() };
+        let root = Type { datatype: DataType::Struct, term: Some("Broken".to_owned()), fields: Some(vec![ghost, int(true, 3), empty]), ..Type::default() };
+        assert_eq!(TypeSchema::from_parts(root, BTreeMap::new()).validate(), Err(vec![
+            SchemaError::MissingTerm("Ghost".to_owned()),
+            SchemaError::InvalidWidth(DataType::Int, 3),
+            SchemaError::EmptyEnum("Empty".to_owned()),
+        ]));
+    }
+
+    #[test]
+    fn normalized_sorts_fields_only_on_request() {
+        #[derive(BorshSchema)]
+        #[allow(dead_code)]
+        struct Forward { a: u8, b: bool, c: Something }
+        #[derive(BorshSchema)]
+        #[allow(dead_code)]
+        struct Backward { c: Something, b: bool, a: u8 }
+        let forward = get_schema::<Forward>();
+        let mut backward = get_schema::<Backward>();
+        backward.schema = Type { name: forward.root().name.clone(), term: forward.root().term.clone(), ..backward.schema };
+        assert_eq!(forward.normalized(false), forward);
+        assert_ne!(forward.normalized(false), backward.normalized(false));
+        assert_eq!(forward.normalized(true), backward.normalized(true));
+    }
+
+    #[test]
+    fn normalized_ignores_term_insertion_order() {
+        let mut terms = BTreeMap::new();
+        let mut reversed = BTreeMap::new();
+        let tsch = get_schema::<Person>();
+        for (term, t) in tsch.terms() {
+            terms.insert(term.clone(), t.clone());
+        }
+        for (term, t) in tsch.terms().iter().rev() {
+            reversed.insert(term.clone(), t.clone());
+        }
+        let a = TypeSchema::from_parts(tsch.root().clone(), terms);
+        let b = TypeSchema::from_parts(tsch.root().clone(), reversed);
+        assert_eq!(a.normalized(true), b.normalized(true));
+        assert_eq!(a.normalized(false).to_borsh(), b.normalized(false).to_borsh());
+    }
+
+    #[test]
+    fn walk_mut_visits_root_and_terms() {
+        let mut tsch = get_schema::<Person>();
+        let mut visited = Vec::new();
+        tsch.walk_mut(|node| visited.push(node.name.clone().unwrap_or_default()));
+        assert_eq!(visited.first().map(String::as_str), Some("Person"));
+        assert!(visited.iter().any(|name| name == "vector"));
+        assert!(visited.iter().any(|name| name == "label"));
+        assert!(visited.iter().any(|name| name == "B"));
+        tsch.walk_mut(|node| if node.datatype == DataType::String { node.datatype = DataType::Bool });
+        assert_eq!(tsch.field_type("name").unwrap().datatype, DataType::Bool);
+        assert_eq!(tsch.field_type("thing.label").unwrap().datatype, DataType::Bool);
+    }
+
+    #[test]
+    fn walk_mut_descends_into_replaced_children() {
+        let mut tsch = get_schema::<Person>();
+        let other = tsch.terms()["Other"].clone();
+        let mut labels = 0;
+        tsch.walk_mut(|node| {
+            if node.name.as_deref() == Some("thing") {
+                *node = Type { name: node.name.clone(), ..other.clone() };
             }
+            if node.datatype == DataType::String && node.name.as_deref() == Some("label") {
+                labels += 1;
+            }
+        });
+        // once through the inlined `thing` and once in the `Other` term
+        assert_eq!(labels, 2);
+    }
+
+    #[test]
+    fn field_type_resolves_dotted_paths() {
+        let tsch = get_schema::<Person>();
+        let thing = tsch.field_type("thing").unwrap();
+        assert!(is_reference(thing));
+        assert_eq!(thing.term.as_deref(), Some("Other"));
+        assert_eq!(tsch.field_type("thing.label").unwrap().datatype, DataType::String);
+        assert_eq!(tsch.field_type("others.*.some").unwrap().term.as_deref(), Some("Something"));
+        let element = tsch.field_type("vector.0.1").unwrap();
+        assert_eq!((element.datatype.clone(), element.length), (DataType::Int, Some(8)));
+        assert_eq!(tsch.field_type("thing.missing"), None);
+        assert_eq!(tsch.field_type("name.inner"), None);
+    }
+
+    #[test]
+    fn datatype_round_trips_through_u8() {
+        for value in 0..15 {
+            assert_eq!(DataType::from_u8(value).unwrap().as_u8(), value);
         }
+        assert_eq!(DataType::Undefined.as_u8(), 14);
+        assert_eq!(DataType::from_u8(15), None);
+        assert_eq!(DataType::from_u8(u8::MAX), None);
     }
-}
 
+    #[test]
+    fn borsh_round_trip() {
+        for tsch in [get_schema::<Person>(), TypeSchema::from_container(&linked_list_container()).unwrap()] {
+            let bytes = tsch.to_borsh();
+            assert_eq!(TypeSchema::from_borsh(&bytes).unwrap(), tsch);
+            assert!(TypeSchema::from_borsh(&bytes[..bytes.len() - 1]).is_err());
+        }
+    }
+
+    #[test]
+    fn unit_structs_stay_structs_and_unit_variants_stay_variants() {
+        #[derive(BorshSchema)]
+        struct Unit;
+        #[derive(BorshSchema)]
+        #[allow(dead_code)]
+        enum E { A }
+        #[derive(BorshSchema)]
+        #[allow(dead_code)]
+        struct Holder { unit: Unit, e: E }
+        let tsch = get_schema::<Unit>();
+        assert_eq!((tsch.root().datatype.clone(), tsch.root().term.as_deref()), (DataType::Struct, Some("Unit")));
+        assert_eq!(tsch.root().fields, Some(Vec::new()));
+        let tsch = get_schema::<Holder>();
+        assert_eq!(tsch.terms()["Unit"].datatype, DataType::Struct);
+        assert_eq!(tsch.terms()["Unit"].fields, Some(Vec::new()));
+        let variants = tsch.terms()["E"].fields.as_ref().unwrap();
+        assert_eq!(variants, &vec![Type { datatype: DataType::Variant, name: Some("A".to_owned()), ..Type::default() }]);
+        assert_eq!(tsch.class_terms().into_iter().map(|(term, _)| term).collect::<Vec<_>>(), vec!["E", "Holder", "Unit"]);
+    }
+
+    #[test]
+    fn iter_pruned_stops_at_pruned_nodes() {
+        #[derive(BorshSchema)]
+        #[allow(dead_code)]
+        struct Team { lead: Other, name: String, size: u8 }
+        let tsch = get_schema::<Team>();
+        let paths: Vec<String> = tsch.iter_pruned(|t| t.datatype == DataType::Struct).map(|(_, _, path)| path).collect();
+        assert_eq!(paths, vec!["", "lead", "name", "size"]);
+        // unpruned, `lead` expands to its three fields and the two variants of `some`
+        assert_eq!(TypeIterator::<()>::new(&tsch).count(), 9);
+        let tsch = get_schema::<Person>();
+        let paths: Vec<String> = tsch.iter_pruned(|t| t.datatype.is_container()).map(|(_, _, path)| path).collect();
+        assert_eq!(paths, vec!["", "name", "uuid", "thing", "vector", "others"]);
+    }
+
+    #[test]
+    fn size_bytes_of_fixed_and_variable_types() {
+        #[derive(BorshSchema)]
+        #[allow(dead_code)]
+        struct Fixed { a: u8, b: i64, c: bool, d: [u16; 3], e: Something, f: (u32, f32) }
+        let tsch = get_schema::<Fixed>();
+        assert_eq!(tsch.root().size_bytes(&tsch), Some(1 + 8 + 1 + 6 + 1 + 8));
+        let tsch = get_schema::<Person>();
+        assert_eq!(tsch.root().size_bytes(&tsch), None);
+        assert_eq!(tsch.field_type("uuid").unwrap().size_bytes(&tsch), Some(16));
+        let tsch = TypeSchema::from_container(&linked_list_container()).unwrap();
+        assert_eq!(tsch.root().size_bytes(&tsch), None);
+    }
+
+    #[test]
+    fn structurally_eq_ignores_names() {
+        #[derive(BorshSchema)]
+        #[allow(dead_code)]
+        struct Point { x: u32, y: u32, tag: Other }
+        #[derive(BorshSchema)]
+        #[allow(dead_code)]
+        struct Size { width: u32, height: u32, label: Other }
+        #[derive(BorshSchema)]
+        #[allow(dead_code)]
+        struct Signed { x: i32, y: u32, tag: Other }
+        let (point, size, signed) = (get_schema::<Point>(), get_schema::<Size>(), get_schema::<Signed>());
+        assert!(point.root().structurally_eq(size.root(), &point, &size));
+        assert!(!point.root().structurally_eq(signed.root(), &point, &signed));
+    }
+
+    #[test]
+    fn structurally_eq_terminates_on_recursive_types() {
+        let mut definitions = borsh::maybestd::collections::HashMap::new();
+        definitions.insert("Node".to_owned(), Definition::Struct {
+            fields: Fields::NamedFields(vec![("data".to_owned(), "u32".to_owned()), ("rest".to_owned(), "Option<Node>".to_owned())]),
+        });
+        definitions.insert("Option<Node>".to_owned(), Definition::Enum {
+            variants: vec![("None".to_owned(), "nil".to_owned()), ("Some".to_owned(), "Node".to_owned())],
+        });
+        let node = TypeSchema::from_container(&BorshSchemaContainer { declaration: "Node".to_owned(), definitions }).unwrap();
+        let list = TypeSchema::from_container(&linked_list_container()).unwrap();
+        assert!(list.root().structurally_eq(node.root(), &list, &node));
+        assert!(!list.root().structurally_eq(get_schema::<Person>().root(), &list, &get_schema::<Person>()));
+    }
+
+    #[test]
+    fn to_rust_type_string_round_trips_declarations() {
+        #[derive(BorshSchema)]
+        #[allow(dead_code)]
+        struct Mixed {
+            count: i16,
+            names: Vec<String>,
+            index: std::collections::HashMap<String, u32>,
+            maybe: Option<Other>,
+            pair: (u8, bool),
+            grid: [f64; 2],
+        }
+        let tsch = get_schema::<Mixed>();
+        let rust = |path: &str| tsch.field_type(path).unwrap().to_rust_type_string(&tsch);
+        assert_eq!(rust("count"), "i16");
+        assert_eq!(rust("names"), "Vec<String>");
+        assert_eq!(rust("index"), "HashMap<String, u32>");
+        assert_eq!(rust("maybe"), "Option<Other>");
+        assert_eq!(rust("pair"), "(u8, bool)");
+        assert_eq!(rust("grid"), "[f64; 2]");
+        assert_eq!(tsch.root().to_rust_type_string(&tsch), "Mixed");
+    }
+
+    #[test]
+    fn schema_errors_display_their_details() {
+        let messages: Vec<String> = [
+            SchemaError::UnknownDeclaration("Foo".to_owned()),
+            SchemaError::Parse("unexpected end".to_owned()),
+            SchemaError::MissingTerm("Ghost".to_owned()),
+            SchemaError::InvalidWidth(DataType::Int, 3),
+            SchemaError::EmptyEnum("Never".to_owned()),
+            SchemaError::Conflict { term: "Other".to_owned() },
+            SchemaError::DuplicateField { term: "Person".to_owned(), name: "name".to_owned() },
+            SchemaError::InvalidDefinition("Vec<u8>".to_owned()),
+            SchemaError::Io(ErrorKind::NotFound, "missing".to_owned()),
+        ].iter().map(ToString::to_string).collect();
+        assert_eq!(messages, vec![
+            "Unknown declaration: Foo",
+            "Could not parse schema source: unexpected end",
+            "Reference to undefined term: Ghost",
+            "Invalid width for Int: 3 bytes",
+            "Enum has no variants: Never",
+            "Conflicting definitions for term: Other",
+            "Duplicate field name in Person: name",
+            "Definition does not match its declaration: Vec<u8>",
+            "I/O error (NotFound): missing",
+        ]);
+    }
+
+    #[test]
+    fn io_errors_convert_to_schema_errors() {
+        let error = SchemaError::from(Error::new(ErrorKind::InvalidData, "bad bytes"));
+        assert_eq!(error, SchemaError::Io(ErrorKind::InvalidData, "bad bytes".to_owned()));
+    }
+
+    /// A type whose schema declares a name `get_type` doesn't know and defines nothing for it.
+    struct Timestamp;
+
+    impl BorshSchemaTrait for Timestamp {
+        fn add_definitions_recursively(_definitions: &mut borsh::maybestd::collections::HashMap<String, Definition>) {}
+
+        fn declaration() -> String {
+            "Timestamp".to_owned()
+        }
+    }
+
+    #[test]
+    fn get_schema_strict_rejects_unknown_declarations() {
+        #[derive(BorshSchema)]
+        #[allow(dead_code)]
+        struct Event { name: String, at: Timestamp }
+        assert_eq!(get_schema_strict::<Event>(), Err(SchemaError::UnknownDeclaration("Timestamp".to_owned())));
+        assert_eq!(get_schema_strict::<Person>(), Ok(get_schema::<Person>()));
+    }
+
+    #[test]
+    fn unhandled_declarations_keep_name_and_term() {
+        #[derive(BorshSchema)]
+        #[allow(dead_code)]
+        struct Event { name: String, at: Timestamp }
+        let tsch = get_schema::<Event>();
+        assert_eq!(tsch.field_type("at"), Some(&Type {
+            datatype: DataType::Undefined,
+            name: Some("at".to_owned()),
+            term: Some("Timestamp".to_owned()),
+            ..Type::default()
+        }));
+        assert_eq!(tsch.validate(), Err(vec![SchemaError::UnknownDeclaration("Timestamp".to_owned())]));
+    }
+
+    #[test]
+    fn string_declarations_map_to_string() {
+        let mut definitions = borsh::maybestd::collections::HashMap::new();
+        definitions.insert("Named".to_owned(), Definition::Struct {
+            fields: Fields::NamedFields(vec![("new".to_owned(), "String".to_owned()), ("old".to_owned(), "string".to_owned())]),
+        });
+        let tsch = TypeSchema::from_container(&BorshSchemaContainer { declaration: "Named".to_owned(), definitions }).unwrap();
+        assert_eq!(tsch.field_type("new").unwrap().datatype, DataType::String);
+        assert_eq!(tsch.field_type("old").unwrap().datatype, DataType::String);
+        assert_eq!(get_schema::<Person>().field_type("name").unwrap().datatype, DataType::String);
+    }
+
+    #[test]
+    fn datatype_classification_covers_every_variant() {
+        let classes: Vec<(DataType, bool, bool, bool)> = (0..15)
+            .map(|value| DataType::from_u8(value).unwrap())
+            .map(|d| (d.clone(), d.is_container(), d.is_numeric(), d.is_leaf()))
+            .collect();
+        assert_eq!(classes, vec![
+            (DataType::Bool, false, false, true),
+            (DataType::Int, false, true, true),
+            (DataType::Float, false, true, true),
+            (DataType::String, false, false, true),
+            (DataType::Enum, true, false, false),
+            (DataType::Variant, true, false, false),
+            (DataType::Tuple, true, false, false),
+            (DataType::Struct, true, false, false),
+            (DataType::Array, true, false, false),
+            (DataType::Vec, true, false, false),
+            (DataType::Option, true, false, false),
+            (DataType::Result, true, false, false),
+            (DataType::HashSet, true, false, false),
+            (DataType::HashMap, true, false, false),
+            (DataType::Undefined, false, false, false),
+        ]);
+    }
+
+    #[test]
+    fn try_from_container_matches_from_container() {
+        let container = Person::schema_container();
+        assert_eq!(TypeSchema::try_from(&container), Ok(get_schema::<Person>()));
+        let list: core::result::Result<TypeSchema, SchemaError> = (&linked_list_container()).try_into();
+        assert_eq!(list, TypeSchema::from_container(&linked_list_container()));
+        let dangling = BorshSchemaContainer { declaration: "Missing".to_owned(), definitions: Default::default() };
+        assert_eq!(TypeSchema::try_from(&dangling), Err(SchemaError::UnknownDeclaration("Missing".to_owned())));
+    }
+
+    #[cfg(feature = "debug-schema")]
+    #[test]
+    fn borsh_container_json_shows_raw_definitions() {
+        let json: serde_json::Value = serde_json::from_str(&borsh_container_json::<Person>()).unwrap();
+        assert_eq!(json["declaration"], "Person");
+        assert_eq!(json["definitions"]["Person"]["Struct"]["fields"][1], serde_json::json!(["uuid", "u128"]));
+        assert_eq!(json["definitions"]["Vec<Other>"], serde_json::json!({ "Sequence": { "elements": "Other" } }));
+        assert_eq!(json["definitions"]["Something"]["Enum"]["variants"][0], serde_json::json!(["A", "SomethingA"]));
+        assert_eq!(json["definitions"]["SomethingA"], serde_json::json!({ "Struct": { "fields": null } }));
+    }
+
+    #[test]
+    fn exporter_helpers() {
+        #[derive(BorshSchema)]
+        #[allow(dead_code)]
+        struct Blob { data: Vec<u8>, words: Vec<u16>, index: std::collections::HashMap<String, Other> }
+        let tsch = get_schema::<Blob>();
+        assert!(tsch.field_type("data").unwrap().is_byte_vec());
+        assert!(!tsch.field_type("words").unwrap().is_byte_vec());
+        let (key, value) = tsch.field_type("index").unwrap().map_entry(&tsch).unwrap();
+        assert_eq!((key.datatype.clone(), value.term.as_deref()), (DataType::String, Some("Other")));
+        assert_eq!(tsch.field_type("data").unwrap().map_entry(&tsch), None);
+        assert_eq!(identifier("Wrapper<string>"), "Wrapper_string_");
+        assert_eq!(identifier("snake_case9"), "snake_case9");
+    }
+}